@@ -0,0 +1,74 @@
+use alloc::rc::Rc;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+/// The allocator used by an arena to obtain memory for every garbage collected object.
+///
+/// By default, arenas use `Global`, which forwards to the global allocator, but a different
+/// allocator may be given when constructing an arena with `new_in` or `try_new_in`.  Small objects
+/// are packed together into pages which are requested from the allocator, larger objects are
+/// requested from the allocator individually.  Every allocation made by an arena is returned to the
+/// same allocator, either once it is no longer in use after a collection or when the arena is
+/// dropped.
+///
+/// # Safety
+///
+/// Implementations must behave like `core::alloc::GlobalAlloc`: a successful call to `allocate`
+/// must return a pointer to a block of memory fitting the given layout, which stays valid until it
+/// is passed back to `deallocate` with that same layout.
+pub unsafe trait GcAllocator {
+    /// Allocate a block of memory fitting the given layout, or return `None` on failure.  The
+    /// layout is never zero sized.
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Return a block of memory previously returned from `allocate` with the given layout.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned from a call to `allocate` on this allocator with the same
+    /// `layout`, and must not have been deallocated already.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The default `GcAllocator`, which uses the global allocator.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Global;
+
+unsafe impl GcAllocator for Global {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        unsafe { NonNull::new(alloc::alloc::alloc(layout)) }
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::alloc::dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+// Allocators are owned by their arena, these impls allow an allocator to be shared with (and
+// inspected from) outside of it.
+
+unsafe impl<A: GcAllocator + ?Sized> GcAllocator for &A {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        (**self).allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+unsafe impl<A: GcAllocator + ?Sized> GcAllocator for Rc<A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+        (**self).allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
@@ -1,16 +1,114 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug};
+use core::mem::ManuallyDrop;
 use core::{f64, usize};
 
-use crate::context::{Context, MutationContext};
+use crate::allocator::{GcAllocator, Global};
+use crate::census::HeapCensus;
+use crate::collect::Collect;
+use crate::context::{CollectionPhase, Context, MutationContext};
+use crate::gc::Gc;
+use crate::gc_weak::GcWeak;
+use crate::rootable::{Root, Rootable};
+use crate::snapshot::HeapSnapshot;
+use crate::stats::{CollectionSummary, CollectorStats, TypeStats};
 
 #[derive(Debug, Clone)]
 pub struct ArenaParameters {
     pub(crate) pause_factor: f64,
     pub(crate) timing_factor: f64,
     pub(crate) min_sleep: usize,
+    pub(crate) nursery_size: Option<usize>,
+    pub(crate) stress: bool,
+    #[cfg(feature = "poison")]
+    pub(crate) quarantine_cycles: u64,
+    pub(crate) max_heap_size: Option<usize>,
+    pub(crate) collection_hook: Option<CollectionHook>,
+    pub(crate) heap_pressure_hook: Option<HeapPressureHook>,
+}
+
+/// An event in the life of the garbage collector, reported to the hook set with
+/// `ArenaParameters::set_collection_hook`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CollectionEvent {
+    /// The collector has moved from one phase to another.  The collector leaving the `Sleep` phase
+    /// means that a new cycle is due, but no work is done for it until collection is next
+    /// requested.
+    PhaseChanged {
+        from: CollectionPhase,
+        to: CollectionPhase,
+    },
+    /// Work has begun on a new full collection cycle, the root is about to be traced.
+    CycleStarted,
+    /// A full collection cycle has completed, and `CollectorStats::last_cycle` holds its results.
+    CycleCompleted,
+    /// A minor collection of the nursery has completed.
+    MinorCollectionCompleted,
+}
+
+type HookFn = dyn Fn(CollectionEvent, &CollectorStats);
+
+#[derive(Clone)]
+pub(crate) struct CollectionHook(Rc<HookFn>);
+
+impl CollectionHook {
+    pub(crate) fn call(&self, event: CollectionEvent, stats: &CollectorStats) {
+        (self.0)(event, stats)
+    }
+}
+
+impl Debug for CollectionHook {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(CollectionHook)")
+    }
+}
+
+/// The error returned by fallible allocation such as `Gc::try_allocate` when an allocation would
+/// take the arena past the maximum heap size set with `ArenaParameters::set_max_heap_size`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct HeapLimitExceeded {
+    /// The size in bytes of the refused allocation.
+    pub requested: usize,
+    /// The total size in bytes of everything allocated at the time, see `total_allocated`.
+    pub total_allocated: usize,
+    pub max_heap_size: usize,
+}
+
+impl fmt::Display for HeapLimitExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "allocation of {} bytes would exceed the maximum heap size of {} bytes, with {} bytes \
+             already allocated",
+            self.requested, self.max_heap_size, self.total_allocated
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeapLimitExceeded {}
+
+type HeapPressureFn = dyn Fn(&HeapLimitExceeded);
+
+#[derive(Clone)]
+pub(crate) struct HeapPressureHook(Rc<HeapPressureFn>);
+
+impl HeapPressureHook {
+    pub(crate) fn call(&self, error: &HeapLimitExceeded) {
+        (self.0)(error)
+    }
+}
+
+impl Debug for HeapPressureHook {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(HeapPressureHook)")
+    }
 }
 
 /// Creates a default ArenaParameters with `pause_factor` set to 0.5, `timing_factor` set to 1.5,
-/// and `min_sleep` set to 4096.
+/// `min_sleep` set to 4096, generational collection and stress mode disabled, no maximum heap
+/// size, and no hooks.
 impl Default for ArenaParameters {
     fn default() -> ArenaParameters {
         const PAUSE_FACTOR: f64 = 0.5;
@@ -21,6 +119,13 @@ impl Default for ArenaParameters {
             pause_factor: PAUSE_FACTOR,
             timing_factor: TIMING_FACTOR,
             min_sleep: MIN_SLEEP,
+            nursery_size: None,
+            stress: false,
+            #[cfg(feature = "poison")]
+            quarantine_cycles: 2,
+            max_heap_size: None,
+            collection_hook: None,
+            heap_pressure_hook: None,
         }
     }
 }
@@ -53,16 +158,99 @@ impl ArenaParameters {
         self.min_sleep = min_sleep;
         self
     }
+
+    /// Enables generational collection with a nursery of the given size in bytes, or disables it if
+    /// set to `None`.
+    ///
+    /// While the garbage collector is sleeping, new objects are considered young and are tracked in
+    /// a separate nursery.  Once the nursery has grown larger than `nursery_size`, calling
+    /// `collect_debt` will perform a minor collection, which only traces and sweeps objects in the
+    /// nursery, promoting every survivor out of it.  This is much cheaper than a full collection
+    /// cycle when most objects die young.  When a full collection cycle begins, the nursery is
+    /// promoted wholesale and collected along with the rest of the heap.
+    pub fn set_nursery_size(mut self, nursery_size: Option<usize>) -> ArenaParameters {
+        self.nursery_size = nursery_size;
+        self
+    }
+
+    /// Enables or disables stress mode, for testing.
+    ///
+    /// In stress mode, every call to `collect_debt` runs a complete collection cycle regardless of
+    /// allocation debt, and a new cycle begins as soon as anything is allocated after one
+    /// completes.  This is extremely slow, but makes collection happen at every opportunity, so
+    /// that bugs such as a `Collect` implementation that fails to trace a pointer show up
+    /// deterministically rather than only when collection happens at an unlucky time.
+    pub fn set_stress(mut self, stress: bool) -> ArenaParameters {
+        self.stress = stress;
+        self
+    }
+
+    /// The number of full collection cycles that freed objects are kept in quarantine for, only
+    /// available with the "poison" feature.  Defaults to 2.
+    ///
+    /// Rather than returning the memory of a freed object to the allocator immediately, its
+    /// contents are overwritten with a recognizable pattern and the allocation is kept until this
+    /// many more cycles have completed.  Any dereference of a `Gc` or upgrade of a `GcWeak`
+    /// pointing to such an object panics, which catches pointers left dangling by a `Collect`
+    /// implementation that failed to trace them.  Setting this to 0 frees objects immediately, and
+    /// disables poisoning.
+    #[cfg(feature = "poison")]
+    pub fn set_quarantine_cycles(mut self, quarantine_cycles: u64) -> ArenaParameters {
+        self.quarantine_cycles = quarantine_cycles;
+        self
+    }
+
+    /// Sets a hard limit on `total_allocated`, including any external memory, or removes it if set
+    /// to `None`.
+    ///
+    /// An allocation which would exceed the limit is refused.  Fallible allocation methods such as
+    /// `Gc::try_allocate` return `HeapLimitExceeded`, and all others panic.  Garbage cannot be
+    /// collected during `mutate`, so a refused allocation instead makes the next call to
+    /// `collect_debt` perform an emergency collection, regardless of allocation debt.  This
+    /// finishes any cycle already in progress and then runs one more complete cycle.  Objects which
+    /// are resurrected into a `FinalizationQueue` or held in quarantine by the "poison" feature are
+    /// not freed by it.
+    pub fn set_max_heap_size(mut self, max_heap_size: Option<usize>) -> ArenaParameters {
+        self.max_heap_size = max_heap_size;
+        self
+    }
+
+    /// Sets a callback which is called whenever an allocation is refused because it would exceed
+    /// the maximum heap size, before the allocation fails or panics.
+    ///
+    /// Like the collection hook, this has no access to the arena, but may for example record that
+    /// a script should be stopped once the current `mutate` call returns.
+    pub fn set_heap_pressure_hook(
+        mut self,
+        hook: impl Fn(&HeapLimitExceeded) + 'static,
+    ) -> ArenaParameters {
+        self.heap_pressure_hook = Some(HeapPressureHook(Rc::new(hook)));
+        self
+    }
+
+    /// Sets a callback which is called for every `CollectionEvent`, along with the collector's
+    /// statistics at the time of the event.
+    ///
+    /// The hook is called from inside collection, and also from allocation when an allocation
+    /// causes the collector to wake, so it should return quickly.  It has no access to the arena.
+    pub fn set_collection_hook(
+        mut self,
+        hook: impl Fn(CollectionEvent, &CollectorStats) + 'static,
+    ) -> ArenaParameters {
+        self.collection_hook = Some(CollectionHook(Rc::new(hook)));
+        self
+    }
 }
 
-/// Creates a new "garbage collected arena" type.  The macro takes two parameters, the name you
-/// would like to give the arena type, and the type of the arena root.  The root type must implement
-/// the `Collect` trait, and be a type that takes a single generic lifetime parameter which is used
-/// for any held `Gc` pointer types.
+/// A "garbage collected arena", holding a root object and every object reachable from it.
 ///
-/// An eample:
+/// The type of the root is given by a `Rootable` type, which may be produced with the `Rootable!`
+/// macro.  The root type must implement the `Collect` trait, and may take any number of other
+/// generic parameters alongside the `'gc` lifetime used for any held `Gc` pointer types.
+///
+/// An example:
 /// ```
-/// # use gc_arena::{Collect, Gc, make_arena};
+/// # use gc_arena::{Arena, ArenaParameters, Collect, Gc, Rootable};
 /// #
 /// # fn main() {
 /// #[derive(Collect)]
@@ -70,7 +258,13 @@ impl ArenaParameters {
 /// struct MyRoot<'gc> {
 ///     ptr: Gc<'gc, i32>,
 /// }
-/// make_arena!(MyArena, MyRoot);
+///
+/// type MyArena = Arena<Rootable!['gc => MyRoot<'gc>]>;
+///
+/// let arena = MyArena::new(ArenaParameters::default(), |mc| MyRoot {
+///     ptr: Gc::allocate(mc, 42),
+/// });
+/// arena.mutate(|_, root| assert_eq!(*root.ptr, 42));
 /// # }
 /// ```
 ///
@@ -91,133 +285,212 @@ impl ArenaParameters {
 /// this way, incremental garbage collection can be achieved (assuming "sufficiently small" calls to
 /// `mutate`) that is both extremely safe and zero overhead vs what you would write in C with raw
 /// pointers and manually ensuring that invariants are held.
-#[macro_export]
-macro_rules! make_arena {
-    ($arena:ident, $root:ident) => {
-        make_arena!(@impl pub(self) $arena, $root);
-    };
-
-    ($v:vis $arena:ident, $root:ident) => {
-        make_arena!(@impl $v $arena, $root);
-    };
-
-    (@impl $v:vis $arena:ident, $root:ident) => {
-        $v struct $arena {
-            context: $crate::Context,
-            root: ::core::mem::ManuallyDrop<$root<'static>>,
-        }
-
-        impl $arena {
-            /// Create a new arena with the given garbage collector tuning parameters.  You must
-            /// provide a closure that accepts a `MutationContext` and returns the appropriate root.
-            /// The held root type is immutable inside the arena, in order to provide mutation, you
-            /// must use `GcCell` types inside the root.
-            #[allow(unused)]
-            pub fn new<F>(arena_parameters: $crate::ArenaParameters, f: F) -> $arena
-            where
-                F: for<'gc> FnOnce($crate::MutationContext<'gc, '_>) -> $root<'gc>,
-            {
-                unsafe {
-                    let context = $crate::Context::new(arena_parameters);
-                    let root: $root<'static> = ::std::mem::transmute(f(context.mutation_context()));
-                    $arena {
-                        context: context,
-                        root: ::core::mem::ManuallyDrop::new(root),
-                    }
-                }
-            }
+pub struct Arena<R: for<'a> Rootable<'a>> {
+    context: Context,
+    // The root is stored with a `'static` brand, but is only ever handed out to callbacks which must
+    // accept any `'gc` lifetime.
+    root: ManuallyDrop<Root<'static, R>>,
+}
 
-            /// Similar to `new`, but allows for constructor that can fail.
-            #[allow(unused)]
-            pub fn try_new<F, E>(
-                arena_parameters: $crate::ArenaParameters,
-                f: F,
-            ) -> Result<$arena, E>
-            where
-                F: for<'gc> FnOnce($crate::MutationContext<'gc, '_>) -> Result<$root<'gc>, E>,
-            {
-                unsafe {
-                    let context = $crate::Context::new(arena_parameters);
-                    let root: $root = f(context.mutation_context())?;
-                    let root: $root<'static> = ::std::mem::transmute(root);
-                    Ok($arena {
-                        context: context,
-                        root: ::core::mem::ManuallyDrop::new(root),
-                    })
-                }
-            }
+impl<R> Arena<R>
+where
+    R: for<'a> Rootable<'a>,
+    for<'a> Root<'a, R>: Collect,
+{
+    /// Create a new arena with the given garbage collector tuning parameters.  You must provide a
+    /// closure that accepts a `MutationContext` and returns the appropriate root.
+    pub fn new<F>(arena_parameters: ArenaParameters, f: F) -> Arena<R>
+    where
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>) -> Root<'gc, R>,
+    {
+        Arena::new_in(arena_parameters, Global, f)
+    }
 
-            /// The primary means of interacting with a garbage collected arena.  Accepts a callback
-            /// which receives a `MutationContext` and a reference to the root, and can return any
-            /// non garbage collected value.  The callback may "mutate" any part of the object graph
-            /// during this call, but no garbage collection will take place during this method.
-            #[allow(unused)]
-            #[inline]
-            pub fn mutate<F, R>(&self, f: F) -> R
-            where
-                F: for<'gc> FnOnce($crate::MutationContext<'gc, '_>, &$root<'gc>) -> R,
-            {
-                unsafe {
-                    f(
-                        self.context.mutation_context(),
-                        ::std::mem::transmute::<&$root<'static>, _>(&*self.root),
-                    )
-                }
+    /// Create a new arena which allocates every object with the given allocator, rather than the
+    /// global allocator.
+    ///
+    /// The allocator is boxed, and the arena type does not depend on it.  `MutationContext` and
+    /// `CollectionContext` appear in every `Collect::trace` signature, so naming the allocator type
+    /// there would force it on every `Collect` impl.  The allocator is only called to obtain or
+    /// release whole pages and large objects, so the dynamic dispatch is not on any per-object path.
+    pub fn new_in<A, F>(arena_parameters: ArenaParameters, allocator: A, f: F) -> Arena<R>
+    where
+        A: GcAllocator + 'static,
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>) -> Root<'gc, R>,
+    {
+        unsafe {
+            let context = Context::new_in(arena_parameters, allocator);
+            let root = f(context.mutation_context());
+            Arena {
+                context,
+                root: ManuallyDrop::new(root),
             }
+        }
+    }
 
-            /// Return total currently used memory
-            #[allow(unused)]
-            #[inline]
-            pub fn total_allocated(&self) -> usize {
-                self.context.total_allocated()
-            }
+    /// Similar to `new`, but allows for constructor that can fail.
+    pub fn try_new<F, E>(arena_parameters: ArenaParameters, f: F) -> Result<Arena<R>, E>
+    where
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>) -> Result<Root<'gc, R>, E>,
+    {
+        Arena::try_new_in(arena_parameters, Global, f)
+    }
 
-            /// When the garbage collector is not sleeping, all allocated objects cause the arena to
-            /// accumulate "allocation debt".  This debt is then be used to time incremental garbage
-            /// collection based on the tuning parameters set in `ArenaParameters`.  The allocation
-            /// debt is measured in bytes, but will generally increase at a rate faster than that of
-            /// allocation so that collection will always complete.
-            #[allow(unused)]
-            #[inline]
-            pub fn allocation_debt(&self) -> f64 {
-                self.context.allocation_debt()
-            }
+    /// Similar to `new_in`, but allows for constructor that can fail.
+    pub fn try_new_in<A, F, E>(
+        arena_parameters: ArenaParameters,
+        allocator: A,
+        f: F,
+    ) -> Result<Arena<R>, E>
+    where
+        A: GcAllocator + 'static,
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>) -> Result<Root<'gc, R>, E>,
+    {
+        unsafe {
+            let context = Context::new_in(arena_parameters, allocator);
+            let root = f(context.mutation_context())?;
+            Ok(Arena {
+                context,
+                root: ManuallyDrop::new(root),
+            })
+        }
+    }
 
-            /// Run the incremental garbage collector until the allocation debt is <= 0.0.  There is
-            /// no minimum unit of work enforced here, so it may be faster to only call this method
-            /// when the allocation debt is above some threshold.
-            #[allow(unused)]
-            #[inline]
-            pub fn collect_debt(&mut self) {
-                unsafe {
-                    let debt = self.context.allocation_debt();
-                    if debt > 0.0 {
-                        self.context.do_collection(&*self.root, debt);
-                    }
-                }
-            }
+    /// The primary means of interacting with a garbage collected arena.  Accepts a callback which
+    /// receives a `MutationContext` and a reference to the root, and can return any non garbage
+    /// collected value.  The callback may "mutate" any part of the object graph during this call,
+    /// but no garbage collection will take place during this method.
+    #[inline]
+    pub fn mutate<F, T>(&self, f: F) -> T
+    where
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>, &Root<'gc, R>) -> T,
+    {
+        unsafe {
+            let r = f(self.context.mutation_context(), &self.root);
+            self.context.verify_barriers(&*self.root);
+            r
+        }
+    }
 
-            /// Run the current garbage collection cycle to completion, stopping once the garbage
-            /// collector has entered the sleeping phase.  If the garbage collector is currently
-            /// sleeping, starts a new cycle and runs that cycle to completion.
-            #[allow(unused)]
-            pub fn collect_all(&mut self) {
-                self.context.wake();
-                unsafe {
-                    self.context
-                        .do_collection(&*self.root, ::std::f64::INFINITY);
-                }
-            }
+    /// Like `mutate`, but gives mutable access to the root object, so that state which is only
+    /// changed from outside the arena does not need to be kept in a `GcCell`.
+    ///
+    /// If the root is changed while a collection is in progress, it is traced again afterwards, so
+    /// it is fine to store newly allocated or otherwise unmarked pointers in it.
+    #[inline]
+    pub fn mutate_root<F, T>(&mut self, f: F) -> T
+    where
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>, &mut Root<'gc, R>) -> T,
+    {
+        unsafe {
+            let r = f(self.context.mutation_context(), &mut self.root);
+            self.context.root_barrier(&*self.root);
+            self.context.verify_barriers(&*self.root);
+            r
         }
+    }
 
-        impl Drop for $arena {
-            fn drop(&mut self) {
-                unsafe {
-                    ::core::mem::ManuallyDrop::drop(&mut self.root);
-                }
-            }
+    /// Return total currently used memory
+    #[inline]
+    pub fn total_allocated(&self) -> usize {
+        self.context.total_allocated()
+    }
+
+    /// When the garbage collector is not sleeping, all allocated objects cause the arena to
+    /// accumulate "allocation debt".  This debt is then be used to time incremental garbage
+    /// collection based on the tuning parameters set in `ArenaParameters`.  The allocation debt is
+    /// measured in bytes, but will generally increase at a rate faster than that of allocation so
+    /// that collection will always complete.
+    #[inline]
+    pub fn allocation_debt(&self) -> f64 {
+        self.context.allocation_debt()
+    }
+
+    /// Returns statistics about the garbage collector, such as the number of allocated objects and
+    /// the results of the last completed collection cycle.
+    #[inline]
+    pub fn stats(&self) -> CollectorStats {
+        self.context.stats()
+    }
+
+    /// Returns allocation statistics for every type ever allocated, sorted by the number of live
+    /// bytes.  Only recorded when the "profiling" feature is enabled, otherwise this is always
+    /// empty.
+    pub fn type_stats(&self) -> Vec<TypeStats> {
+        self.context.type_stats()
+    }
+
+    /// Records every object reachable from the root, along with the pointers between them, for
+    /// debugging.  This does not affect garbage collection in any way, and may be called from within
+    /// `mutate`.
+    ///
+    /// Any `GcCell` or `RefLock` which is mutably borrowed at the time is treated as if it were
+    /// empty, so objects only reachable through it are left out.
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        unsafe { self.context.heap_snapshot(&*self.root) }
+    }
+
+    /// Counts every object reachable from the root by type.  Censuses taken at different times can
+    /// be compared with `HeapCensus::diff` to look for leaks.  Like `heap_snapshot`, this skips the
+    /// contents of any mutably borrowed `GcCell` or `RefLock`.
+    pub fn census(&self) -> HeapCensus {
+        self.heap_snapshot().census()
+    }
+
+    /// Returns the type names along a shortest path of `Gc` pointers from the root to the given
+    /// object, starting with the root's type and ending with the object's, or `None` if it is not
+    /// reachable from the root.  This is what keeps the object alive, which is useful for finding
+    /// out why something has not been collected.
+    ///
+    /// This only searches until the object is found, rather than recording the whole object graph
+    /// as `heap_snapshot` does.  Like `heap_snapshot`, it does not affect garbage collection, may be
+    /// called from within `mutate`, and does not look inside any mutably borrowed `GcCell` or
+    /// `RefLock`.
+    pub fn retaining_path<T: Collect + ?Sized>(&self, gc: Gc<'_, T>) -> Option<Vec<&'static str>> {
+        unsafe { self.context.retaining_path(&*self.root, gc.ptr) }
+    }
+
+    /// Like `retaining_path`, but for the target of a weak pointer.  The weak pointer itself is
+    /// never part of the path.
+    pub fn retaining_path_weak<T: Collect + ?Sized>(
+        &self,
+        weak: GcWeak<'_, T>,
+    ) -> Option<Vec<&'static str>> {
+        self.retaining_path(weak.inner)
+    }
+
+    /// Run the incremental garbage collector until the allocation debt is <= 0.0.  There is no
+    /// minimum unit of work enforced here, so it may be faster to only call this method when the
+    /// allocation debt is above some threshold.
+    ///
+    /// If generational collection is enabled and the collector is sleeping, this will instead
+    /// perform a minor collection once the nursery is full.  In stress mode, this always runs a
+    /// complete collection cycle.
+    ///
+    /// Returns a summary of the collection work performed.
+    #[inline]
+    pub fn collect_debt(&mut self) -> CollectionSummary {
+        unsafe { self.context.collect_debt(&*self.root) }
+    }
+
+    /// Run the current garbage collection cycle to completion, stopping once the garbage collector
+    /// has entered the sleeping phase.  If the garbage collector is currently sleeping, starts a
+    /// new cycle and runs that cycle to completion.
+    ///
+    /// Returns a summary of the collection work performed.
+    pub fn collect_all(&mut self) -> CollectionSummary {
+        unsafe { self.context.collect_all(&*self.root) }
+    }
+}
+
+impl<R: for<'a> Rootable<'a>> Drop for Arena<R> {
+    fn drop(&mut self) {
+        // The root must be dropped while the context, which owns every object it points to, is
+        // still alive.
+        unsafe {
+            ManuallyDrop::drop(&mut self.root);
         }
-    };
+    }
 }
 
 /// Create a temporary arena without a root object and perform the given operation on it.  No
@@ -0,0 +1,124 @@
+use core::ops::Deref;
+
+/// A reference to a value held inside a `Gc` pointer, which proves that the write barrier has
+/// already been executed for the object containing it.
+///
+/// A `&Write<T>` for the entire contents of a `Gc` is obtained with `Gc::write`.  It can then be
+/// projected to any of the fields of `T` with the `field!` macro, since once the write barrier has
+/// been executed for an object it covers every part of it.  Values which provide internal
+/// mutability, like `Lock` and `RefLock`, implement `Unlock` and can only be mutated through a
+/// `&Write` reference, so a single allocation may hold many independently mutable fields.
+#[non_exhaustive]
+#[repr(transparent)]
+pub struct Write<T: ?Sized> {
+    // Public so that the `field!` macro can match on it, `non_exhaustive` prevents constructing a
+    // `Write` outside of this crate.
+    #[doc(hidden)]
+    pub __inner: T,
+}
+
+impl<T: ?Sized> Deref for Write<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.__inner
+    }
+}
+
+impl<T: ?Sized> Write<T> {
+    /// Asserts that the given value may be written to.
+    ///
+    /// # Safety
+    ///
+    /// If the value is held inside a `Gc` pointer, `Gc::write_barrier` must already have been called
+    /// for that pointer during the current arena mutation.
+    #[inline]
+    pub unsafe fn assume(v: &T) -> &Write<T> {
+        &*(v as *const T as *const Write<T>)
+    }
+
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.__inner
+    }
+
+    /// Gives access to the internally mutable interior of a lock type.
+    #[inline]
+    pub fn unlock(&self) -> &T::Unlocked
+    where
+        T: Unlock,
+    {
+        unsafe { self.__inner.unlock_unchecked() }
+    }
+}
+
+/// Types which provide internal mutability, but only once the write barrier has been executed for
+/// the object holding them.
+pub trait Unlock {
+    type Unlocked: ?Sized;
+
+    /// Gives access to the internally mutable interior of this value.
+    ///
+    /// # Safety
+    ///
+    /// Any `Gc` pointers stored through the returned reference must be accompanied by a call to
+    /// `Gc::write_barrier` for the object holding this value.  Prefer `Write::unlock`, which
+    /// guarantees this.
+    unsafe fn unlock_unchecked(&self) -> &Self::Unlocked;
+}
+
+/// Projects a `&Write<T>` to a `&Write` for a single field of `T`.
+///
+/// Takes the `&Write` reference, the type it refers to, and the name of the field (or the index of
+/// a tuple struct field).
+///
+/// ```
+/// # use gc_arena::{field, rootless_arena, Collect, Gc, Lock};
+/// #[derive(Collect)]
+/// #[collect(no_drop)]
+/// struct Counters {
+///     hits: Lock<u32>,
+///     misses: Lock<u32>,
+/// }
+///
+/// rootless_arena(|mc| {
+///     let counters = Gc::allocate(
+///         mc,
+///         Counters {
+///             hits: Lock::new(0),
+///             misses: Lock::new(0),
+///         },
+///     );
+///
+///     let write = Gc::write(mc, counters);
+///     field!(write, Counters, hits).unlock().set(1);
+///     field!(write, Counters, misses).unlock().set(2);
+///     assert_eq!(counters.hits.get() + counters.misses.get(), 3);
+/// });
+/// ```
+#[macro_export]
+macro_rules! field {
+    ($value:expr, $type:path, $field:tt) => {
+        // The pattern only matches a real field of the given type (rather than going through
+        // `Deref`), and every part of a value is covered by the write barrier for the whole.
+        match $value {
+            $crate::Write {
+                __inner:
+                    $type {
+                        $field: ref __field,
+                        ..
+                    },
+                ..
+            } => unsafe { $crate::Write::assume(__field) },
+        }
+    };
+}
+
+/// Shorthand for `field!($value, $type, $field).unlock()`.
+#[macro_export]
+macro_rules! unlock {
+    ($value:expr, $type:path, $field:tt) => {
+        $crate::field!($value, $type, $field).unlock()
+    };
+}
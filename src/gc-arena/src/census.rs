@@ -0,0 +1,146 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt;
+
+use crate::snapshot::HeapSnapshot;
+
+/// The number and total size of every reachable object, by type, at a single point in time.
+///
+/// A census is taken with `census` on an arena or `HeapSnapshot::census`, and two censuses can be
+/// compared with `diff` to find types which grew in between, such as when checking that some
+/// operation does not leak.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapCensus {
+    types: BTreeMap<&'static str, CensusEntry>,
+}
+
+/// The number and total size of the objects of a single type in a `HeapCensus`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CensusEntry {
+    pub count: usize,
+    pub size: usize,
+}
+
+impl HeapCensus {
+    pub(crate) fn new(snapshot: &HeapSnapshot) -> HeapCensus {
+        let mut types = BTreeMap::<&'static str, CensusEntry>::new();
+        for node in &snapshot.nodes()[HeapSnapshot::ROOT + 1..] {
+            let entry = types.entry(node.type_name).or_default();
+            entry.count += 1;
+            entry.size += node.size;
+        }
+        HeapCensus { types }
+    }
+
+    /// The entry for the given type name, which is empty if there were no objects of that type.
+    pub fn get(&self, type_name: &str) -> CensusEntry {
+        self.types.get(type_name).copied().unwrap_or_default()
+    }
+
+    /// Every type with at least one object, ordered by type name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, CensusEntry)> + '_ {
+        self.types
+            .iter()
+            .map(|(&type_name, &entry)| (type_name, entry))
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.types.values().map(|entry| entry.count).sum()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.types.values().map(|entry| entry.size).sum()
+    }
+
+    /// Compares this census with one taken later, returning every type whose count or size
+    /// changed.
+    pub fn diff(&self, later: &HeapCensus) -> CensusDiff {
+        let mut changes: Vec<CensusChange> = self
+            .types
+            .keys()
+            .chain(later.types.keys())
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|type_name| CensusChange {
+                type_name,
+                before: self.get(type_name),
+                after: later.get(type_name),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        changes.sort_by_key(|change| Reverse(change.size_delta()));
+        CensusDiff { changes }
+    }
+}
+
+/// The differences between two `HeapCensus`es, returned by `HeapCensus::diff`.
+///
+/// The `Display` implementation lists every change, one type per line, which is useful as a
+/// message when asserting that nothing grew.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CensusDiff {
+    changes: Vec<CensusChange>,
+}
+
+/// A change in the objects of a single type between two censuses.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CensusChange {
+    pub type_name: &'static str,
+    pub before: CensusEntry,
+    pub after: CensusEntry,
+}
+
+impl CensusChange {
+    pub fn count_delta(&self) -> isize {
+        self.after.count as isize - self.before.count as isize
+    }
+
+    pub fn size_delta(&self) -> isize {
+        self.after.size as isize - self.before.size as isize
+    }
+}
+
+impl CensusDiff {
+    /// Every type whose count or size changed, sorted by the change in size from largest growth to
+    /// largest shrinkage.
+    pub fn changes(&self) -> &[CensusChange] {
+        &self.changes
+    }
+
+    /// Every type whose count or size increased.
+    pub fn growth(&self) -> impl Iterator<Item = &CensusChange> + '_ {
+        self.changes
+            .iter()
+            .filter(|change| change.count_delta() > 0 || change.size_delta() > 0)
+    }
+
+    /// Returns true if any type increased in count or size.
+    pub fn has_growth(&self) -> bool {
+        self.growth().next().is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for CensusDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(
+                f,
+                "{}: {:+} objects ({} -> {}), {:+} bytes ({} -> {})",
+                change.type_name,
+                change.count_delta(),
+                change.before.count,
+                change.after.count,
+                change.size_delta(),
+                change.before.size,
+                change.after.size,
+            )?;
+        }
+        Ok(())
+    }
+}
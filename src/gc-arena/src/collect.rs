@@ -17,7 +17,9 @@ use crate::context::CollectionContext;
 /// `Collect` in such a way that it is possible to store `Gc` pointers inside them, so the write
 /// barrier requirement cannot be broken when procedurally deriving `Collect`.  A safe way of
 /// providing internal mutability in this case is to use `GcCell`, which provides internal
-/// mutability while ensuring that the write barrier is always executed.
+/// mutability while ensuring that the write barrier is always executed.  Fields of a single object
+/// may also be made mutable with `Lock` and `RefLock`, which can only be unlocked through the
+/// `&Write` reference returned by `Gc::write`.
 pub unsafe trait Collect {
     /// As an optimization, if this type can never hold a `Gc` pointer and `trace` is unnecessary to
     /// call, you may implement this method and return false.  The default implementation returns
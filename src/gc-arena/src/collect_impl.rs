@@ -76,7 +76,15 @@ unsafe impl<'a, T: ?Sized> Collect for &'a mut T {
     }
 }
 
-unsafe impl<T: ?Sized + Collect> Collect for Box<T> {
+// `Box<dyn Trait>` is not covered here, since it would overlap with the impls for boxed slices and
+// strings.  Because `Box` is fundamental, crates defining a trait with `Collect` as a supertrait may
+// implement `Collect` for `Box<dyn Trait>` themselves.
+unsafe impl<T: Collect> Collect for Box<T> {
+    #[inline]
+    fn needs_trace() -> bool {
+        T::needs_trace()
+    }
+
     #[inline]
     fn trace(&self, cc: CollectionContext) {
         (**self).trace(cc)
@@ -91,12 +99,30 @@ unsafe impl<T: Collect> Collect for Box<[T]> {
 
     #[inline]
     fn trace(&self, cc: CollectionContext) {
-        for t in self.iter() {
-            t.trace(cc)
+        (**self).trace(cc)
+    }
+}
+
+unsafe impl Collect for Box<str> {
+    #[inline]
+    fn needs_trace() -> bool {
+        false
+    }
+}
+
+unsafe impl<T: Collect> Collect for [T] {
+    #[inline]
+    fn trace(&self, cc: CollectionContext) {
+        if T::needs_trace() {
+            for t in self.iter() {
+                t.trace(cc)
+            }
         }
     }
 }
 
+unsafe impl Collect for str {}
+
 unsafe impl<T: Collect> Collect for Option<T> {
     #[inline]
     fn needs_trace() -> bool {
@@ -1,14 +1,31 @@
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+#[cfg(feature = "poison")]
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use core::alloc::Layout;
 use core::cell::{Cell, RefCell, UnsafeCell};
+#[cfg(feature = "profiling")]
+use core::cmp::Reverse;
 use core::marker::PhantomData;
 use core::mem;
 use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
-use crate::arena::ArenaParameters;
+use crate::allocator::{GcAllocator, Global};
+use crate::arena::{ArenaParameters, CollectionEvent, HeapLimitExceeded};
 use crate::collect::Collect;
-use crate::types::{GcBox, GcColor, GcFlags, Invariant};
+use crate::ephemeron::TraceEphemerons;
+use crate::finalization::Resurrect;
+use crate::gc::Gc;
+use crate::gc_weak::PruneDead;
+use crate::heap::Heap;
+use crate::snapshot::{HeapSnapshot, HeapWalk, PathFinder, SnapshotBuilder};
+use crate::stats::{CollectionSummary, CollectorStats, CycleStats, TypeStats};
+use crate::types::{
+    slice_allocation_layout, slice_box_ptr, GcBox, GcBoxHeader, GcColor, GcFlags, Invariant,
+};
 
 /// Handle value given by arena callbacks during construction and mutation.  Allows allocating new
 /// `Gc` pointers and internally mutating values held by `Gc` pointers.
@@ -23,13 +40,84 @@ impl<'gc, 'context> MutationContext<'gc, 'context> {
         self.context.allocate(t)
     }
 
-    pub(crate) unsafe fn write_barrier<T: 'gc + Collect>(self, ptr: NonNull<GcBox<T>>) {
+    pub(crate) unsafe fn try_allocate<T: 'gc + Collect>(
+        self,
+        t: T,
+    ) -> Result<NonNull<GcBox<T>>, HeapLimitExceeded> {
+        self.context.try_allocate(t)
+    }
+
+    pub(crate) unsafe fn allocate_slice<T: 'gc + Collect>(
+        self,
+        items: Vec<T>,
+    ) -> NonNull<GcBox<[T]>> {
+        self.context.allocate_slice(items)
+    }
+
+    pub(crate) unsafe fn try_allocate_slice<T: 'gc + Collect>(
+        self,
+        items: Vec<T>,
+    ) -> Result<NonNull<GcBox<[T]>>, HeapLimitExceeded> {
+        self.context.try_allocate_slice(items)
+    }
+
+    pub(crate) unsafe fn allocate_str(self, s: &str) -> NonNull<GcBox<str>> {
+        self.context.allocate_str(s)
+    }
+
+    pub(crate) unsafe fn try_allocate_str(
+        self,
+        s: &str,
+    ) -> Result<NonNull<GcBox<str>>, HeapLimitExceeded> {
+        self.context.try_allocate_str(s)
+    }
+
+    pub(crate) unsafe fn write_barrier<T: 'gc + Collect + ?Sized>(self, ptr: NonNull<GcBox<T>>) {
         self.context.write_barrier(ptr)
     }
 
-    pub(crate) unsafe fn upgrade<T: 'gc + Collect>(self, ptr: NonNull<GcBox<T>>) -> bool {
+    /// Reports that the given object owns `size` bytes of memory outside of the arena, such as the
+    /// buffer of a `Vec`, replacing any size previously reported for it.
+    ///
+    /// The size is counted in `total_allocated` and charged as allocation debt just like the
+    /// object's own allocation, so that large external buffers make the collector run sooner.  It
+    /// is released automatically when the collector drops the object's value, or may be released
+    /// early by setting it to 0.
+    pub fn set_external_size<T: 'gc + Collect + ?Sized>(self, gc: Gc<'gc, T>, size: usize) {
+        unsafe { self.context.set_external_size(erase(gc.ptr), size) }
+    }
+
+    /// The number of bytes of external memory last reported for the given object with
+    /// `set_external_size`.
+    pub fn external_size<T: 'gc + Collect + ?Sized>(self, gc: Gc<'gc, T>) -> usize {
+        self.context.external_size(erase(gc.ptr))
+    }
+
+    pub(crate) unsafe fn upgrade<T: 'gc + Collect + ?Sized>(self, ptr: NonNull<GcBox<T>>) -> bool {
         self.context.upgrade(ptr)
     }
+
+    pub(crate) unsafe fn add_finalization_queue<T: 'gc + Resurrect>(self, ptr: NonNull<GcBox<T>>) {
+        self.context.add_finalization_queue(ptr)
+    }
+
+    pub(crate) unsafe fn finalization_barrier<T: 'gc + Collect + ?Sized>(
+        self,
+        ptr: NonNull<GcBox<T>>,
+    ) {
+        self.context.finalization_barrier(ptr)
+    }
+
+    pub(crate) unsafe fn add_ephemeron_table<T: 'gc + TraceEphemerons>(
+        self,
+        ptr: NonNull<GcBox<T>>,
+    ) {
+        self.context.add_ephemeron_table(ptr)
+    }
+
+    pub(crate) unsafe fn add_weak_collection<T: 'gc + PruneDead>(self, ptr: NonNull<GcBox<T>>) {
+        self.context.add_weak_collection(ptr)
+    }
 }
 
 /// Handle value given by arena callbacks during garbage collection, which must be passed through
@@ -40,67 +128,200 @@ pub struct CollectionContext<'context> {
 }
 
 impl<'context> CollectionContext<'context> {
-    pub(crate) unsafe fn trace<T: Collect>(self, ptr: NonNull<GcBox<T>>) {
+    pub(crate) unsafe fn trace<T: Collect + ?Sized>(self, ptr: NonNull<GcBox<T>>) {
         self.context.trace(ptr)
     }
+
+    pub(crate) unsafe fn trace_weak<T: Collect + ?Sized>(self, ptr: NonNull<GcBox<T>>) {
+        self.context.trace_weak(ptr)
+    }
+
+    // Returns true if the given object has been reached by the collector during this cycle.
+    pub(crate) unsafe fn is_marked<T: Collect + ?Sized>(self, ptr: NonNull<GcBox<T>>) -> bool {
+        self.context.is_marked(ptr)
+    }
+
+    // Traces the contents of a `RefCell`.  The heap may be walked from inside `mutate` while the
+    // cell is mutably borrowed, in which case its contents are left out of the walk rather than
+    // panicking.
+    pub(crate) fn trace_ref_cell<T: Collect + ?Sized>(self, cell: &RefCell<T>) {
+        let walking = self.context.walk.borrow().is_some();
+        if walking {
+            if let Ok(value) = cell.try_borrow() {
+                value.trace(self);
+            }
+        } else {
+            cell.borrow().trace(self);
+        }
+    }
 }
 
-// Main gc context type, public because it must be accessible from the `make_arena!` macro.
-#[doc(hidden)]
-pub struct Context {
+// Main gc context type, which owns every object in an `Arena`.
+pub(crate) struct Context {
     parameters: ArenaParameters,
+    heap: Heap,
 
-    phase: Cell<Phase>,
+    phase: Cell<CollectionPhase>,
     total_allocated: Cell<usize>,
     remembered_size: Cell<usize>,
     wakeup_total: Cell<usize>,
     allocation_debt: Cell<f64>,
 
-    all: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
-    sweep: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
-    sweep_prev: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
+    gray: RefCell<Vec<NonNull<GcBoxHeader>>>,
+    gray_again: RefCell<Vec<NonNull<GcBoxHeader>>>,
+
+    // The inner state of every live `FinalizationQueue`, whether finalization has started during the
+    // current propagate phase, and how many of the queues (kept at the front of the list) have
+    // already had their chance to resurrect unreachable objects.
+    finalization_queues: RefCell<Vec<NonNull<GcBox<dyn Resurrect>>>>,
+    resurrected: Cell<bool>,
+    resurrected_queues: Cell<usize>,
+
+    // The inner state of every live `EphemeronTable`, along with a count of every object marked so
+    // far, used to tell whether tracing ephemerons has reached a fixpoint.
+    ephemeron_tables: RefCell<Vec<NonNull<GcBox<dyn TraceEphemerons>>>>,
+    mark_count: Cell<usize>,
+
+    // The inner state of every live `GcWeakMap` and `GcWeakSet`, which are pruned once each sweep
+    // completes.
+    weak_collections: RefCell<Vec<NonNull<GcBox<dyn PruneDead>>>>,
+
+    // Generational collection state, only used when `ArenaParameters::nursery_size` is set.  While
+    // sleeping, every newly allocated object is also recorded in the nursery, so that minor
+    // collections only need to look at young objects.  Old objects which are mutated while sleeping
+    // may now point to young objects, so they are placed into the remembered set (not to be
+    // confused with `remembered_size`, which is the size of objects that survived the last full
+    // cycle).  `minor` is set only during minor collections.
+    nursery: RefCell<Vec<NonNull<GcBoxHeader>>>,
+    nursery_allocated: Cell<usize>,
+    remembered_set: RefCell<Vec<NonNull<GcBoxHeader>>>,
+    minor: Cell<bool>,
+
+    // Collector statistics, see `CollectorStats`.  `cycle` holds the statistics for the cycle in
+    // progress, which become `last_cycle` once it completes.  While `do_collection` is running,
+    // `phase_start` is the time the current phase was entered or the call began, whichever is
+    // later.
+    object_count: Cell<usize>,
+    cycles: Cell<u64>,
+    minor_collections: Cell<u64>,
+    write_barriers: Cell<u64>,
+    objects_freed: Cell<u64>,
+    bytes_freed: Cell<u64>,
+    cycle: Cell<CycleStats>,
+    last_cycle: Cell<CycleStats>,
+    #[cfg(feature = "std")]
+    phase_start: Cell<Option<Instant>>,
+
+    // The sizes of memory owned by objects outside of the heap, see
+    // `MutationContext::set_external_size`.  Each is included in `total_allocated`, and
+    // `external_allocated` is their sum.
+    external_sizes: RefCell<BTreeMap<NonNull<GcBoxHeader>, usize>>,
+    external_allocated: Cell<usize>,
 
-    gray: RefCell<Vec<NonNull<GcBox<dyn Collect>>>>,
-    gray_again: RefCell<Vec<NonNull<GcBox<dyn Collect>>>>,
+    // Set when an allocation is refused because of the maximum heap size, so that the next call to
+    // `collect_debt` frees as much as possible.
+    emergency_collection: Cell<bool>,
+    // Live and total allocations by type name, see `TypeStats`.
+    #[cfg(feature = "profiling")]
+    type_stats: RefCell<BTreeMap<&'static str, TypeStats>>,
+
+    // Only set while checking for missing write barriers, to the type name of the black object
+    // being re-traced.  Tracing then checks each child rather than marking it.
+    #[cfg(feature = "verify-barriers")]
+    verifying: Cell<Option<&'static str>>,
+
+    // Objects which have been freed and poisoned but not yet returned to the allocator, along with
+    // the number of completed cycles at the time they were freed.
+    #[cfg(feature = "poison")]
+    quarantine: RefCell<VecDeque<(NonNull<GcBoxHeader>, u64)>>,
+
+    // Only set while walking the heap for a snapshot or a retaining path, during which tracing
+    // reports pointers to the walk rather than marking anything.
+    walk: RefCell<Option<HeapWalk>>,
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
-        struct DropAll(Option<NonNull<GcBox<dyn Collect>>>);
+        struct DropAll<'a>(&'a Context, Vec<NonNull<GcBoxHeader>>);
 
-        impl Drop for DropAll {
+        impl<'a> Drop for DropAll<'a> {
             fn drop(&mut self) {
                 unsafe {
-                    if let Some(ptr) = self.0.take() {
-                        let mut drop_resume = DropAll(Some(ptr));
-                        while let Some(ptr) = drop_resume.0.take() {
-                            let gc_box = ptr.as_ref();
-                            drop_resume.0 = gc_box.next.get();
-                            Box::from_raw(ptr.as_ptr());
+                    if !self.1.is_empty() {
+                        let mut drop_resume = DropAll(self.0, mem::take(&mut self.1));
+                        while let Some(ptr) = drop_resume.1.pop() {
+                            self.0.free(ptr);
                         }
                     }
                 }
             }
         }
 
-        DropAll(self.all.get());
+        #[cfg(feature = "poison")]
+        for (ptr, _) in self.quarantine.get_mut().drain(..) {
+            unsafe {
+                let (allocation, layout) = GcBoxHeader::allocation(ptr);
+                self.heap.deallocate(allocation, layout);
+            }
+        }
+
+        let mut objects = Vec::new();
+        unsafe {
+            self.heap.for_each_object(|ptr| objects.push(ptr));
+        }
+        DropAll(self, objects);
     }
 }
 
 impl Context {
     pub unsafe fn new(parameters: ArenaParameters) -> Context {
+        Context::new_in(parameters, Global)
+    }
+
+    pub unsafe fn new_in<A: GcAllocator + 'static>(
+        parameters: ArenaParameters,
+        allocator: A,
+    ) -> Context {
         Context {
             parameters,
-            phase: Cell::new(Phase::Wake),
+            heap: Heap::new(Box::new(allocator)),
+            phase: Cell::new(CollectionPhase::Wake),
             total_allocated: Cell::new(0),
             remembered_size: Cell::new(0),
             wakeup_total: Cell::new(0),
             allocation_debt: Cell::new(0.0),
-            all: Cell::new(None),
-            sweep: Cell::new(None),
-            sweep_prev: Cell::new(None),
             gray: RefCell::new(Vec::new()),
             gray_again: RefCell::new(Vec::new()),
+            finalization_queues: RefCell::new(Vec::new()),
+            resurrected: Cell::new(false),
+            resurrected_queues: Cell::new(0),
+            ephemeron_tables: RefCell::new(Vec::new()),
+            mark_count: Cell::new(0),
+            weak_collections: RefCell::new(Vec::new()),
+            nursery: RefCell::new(Vec::new()),
+            nursery_allocated: Cell::new(0),
+            remembered_set: RefCell::new(Vec::new()),
+            minor: Cell::new(false),
+            object_count: Cell::new(0),
+            cycles: Cell::new(0),
+            minor_collections: Cell::new(0),
+            write_barriers: Cell::new(0),
+            objects_freed: Cell::new(0),
+            bytes_freed: Cell::new(0),
+            cycle: Cell::new(CycleStats::default()),
+            last_cycle: Cell::new(CycleStats::default()),
+            #[cfg(feature = "std")]
+            phase_start: Cell::new(None),
+            external_sizes: RefCell::new(BTreeMap::new()),
+            external_allocated: Cell::new(0),
+            emergency_collection: Cell::new(false),
+            #[cfg(feature = "profiling")]
+            type_stats: RefCell::new(BTreeMap::new()),
+            #[cfg(feature = "verify-barriers")]
+            verifying: Cell::new(None),
+            #[cfg(feature = "poison")]
+            quarantine: RefCell::new(VecDeque::new()),
+            walk: RefCell::new(None),
         }
     }
 
@@ -125,10 +346,201 @@ impl Context {
         self.total_allocated.get()
     }
 
+    pub fn stats(&self) -> CollectorStats {
+        CollectorStats {
+            object_count: self.object_count.get(),
+            total_allocated: self.total_allocated.get(),
+            external_allocated: self.external_allocated.get(),
+            cycles: self.cycles.get(),
+            minor_collections: self.minor_collections.get(),
+            write_barriers: self.write_barriers.get(),
+            objects_freed: self.objects_freed.get(),
+            bytes_freed: self.bytes_freed.get(),
+            last_cycle: self.last_cycle.get(),
+        }
+    }
+
+    // Returns allocation statistics for every type ever allocated, sorted by the number of live
+    // bytes from largest to smallest.  Always empty unless the "profiling" feature is
+    // enabled.
+    pub fn type_stats(&self) -> Vec<TypeStats> {
+        #[cfg(feature = "profiling")]
+        {
+            let mut type_stats: Vec<TypeStats> =
+                self.type_stats.borrow().values().copied().collect();
+            type_stats.sort_by_key(|stats| Reverse(stats.live_bytes));
+            type_stats
+        }
+
+        #[cfg(not(feature = "profiling"))]
+        Vec::new()
+    }
+
+    // Run the incremental garbage collector until the allocation debt is <= 0.0, or if the
+    // collector is sleeping, perform a minor collection if one is due.  In stress mode, this
+    // instead always runs a complete collection cycle, and after an allocation has been refused
+    // because of the maximum heap size, this performs an emergency collection.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn collect_debt<R: Collect>(&self, root: &R) -> CollectionSummary {
+        let before = self.stats();
+        let debt = self.allocation_debt();
+        let work_done = if self.emergency_collection.take() {
+            // A cycle which is already in progress cannot free objects which became unreachable
+            // after it began, so a second complete cycle is needed after it.
+            let mut work_done = 0.0;
+            if matches!(
+                self.phase.get(),
+                CollectionPhase::Propagate | CollectionPhase::Sweep
+            ) {
+                work_done += self.do_collection(root, f64::INFINITY);
+            }
+            self.wake();
+            work_done + self.do_collection(root, f64::INFINITY)
+        } else if self.parameters.stress {
+            self.wake();
+            self.do_collection(root, f64::INFINITY)
+        } else if debt > 0.0 {
+            self.do_collection(root, debt)
+        } else {
+            self.collect_nursery(root);
+            0.0
+        };
+        self.summarize(&before, work_done)
+    }
+
+    // Run the current collection cycle to completion, or if the collector is sleeping, start a new
+    // cycle and run that to completion.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn collect_all<R: Collect>(&self, root: &R) -> CollectionSummary {
+        let before = self.stats();
+        self.wake();
+        let work_done = self.do_collection(root, f64::INFINITY);
+        self.summarize(&before, work_done)
+    }
+
+    fn summarize(&self, before: &CollectorStats, work_done: f64) -> CollectionSummary {
+        let after = self.stats();
+        CollectionSummary {
+            work_done,
+            objects_freed: after.objects_freed - before.objects_freed,
+            bytes_freed: after.bytes_freed - before.bytes_freed,
+            cycles_completed: after.cycles - before.cycles,
+            minor_collections: after.minor_collections - before.minor_collections,
+        }
+    }
+
+    // Walks every object reachable from the given root and records the object graph, without
+    // affecting the state of the collector.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn heap_snapshot<R: Collect>(&self, root: &R) -> HeapSnapshot {
+        match self.walk_heap(root, HeapWalk::Snapshot(SnapshotBuilder::new(root))) {
+            HeapWalk::Snapshot(builder) => builder.finish(),
+            HeapWalk::Path(_) => unreachable!(),
+        }
+    }
+
+    // Returns the type names along a shortest path of strong pointers from the given root to the
+    // given object, without affecting the state of the collector or recording the rest of the
+    // object graph.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn retaining_path<R: Collect, T: Collect + ?Sized>(
+        &self,
+        root: &R,
+        target: NonNull<GcBox<T>>,
+    ) -> Option<Vec<&'static str>> {
+        match self.walk_heap(root, HeapWalk::Path(PathFinder::new::<R>(erase(target)))) {
+            HeapWalk::Path(finder) => finder.finish(),
+            HeapWalk::Snapshot(_) => unreachable!(),
+        }
+    }
+
+    unsafe fn walk_heap<R: Collect>(&self, root: &R, walk: HeapWalk) -> HeapWalk {
+        let cc = CollectionContext { context: self };
+        *self.walk.borrow_mut() = Some(walk);
+        root.trace(cc);
+
+        loop {
+            if self.walk.borrow().as_ref().unwrap().is_done() {
+                break;
+            }
+            let next = self.walk.borrow_mut().as_mut().unwrap().next_untraced();
+            if let Some(ptr) = next {
+                if ptr.as_ref().flags.needs_trace() {
+                    GcBoxHeader::trace_value(ptr, cc);
+                }
+                continue;
+            }
+
+            // Just like marking, the values of ephemerons whose keys have been reached may reach
+            // further keys, so this must be repeated until nothing new is found.
+            let visited_count = self.walk.borrow().as_ref().unwrap().visited_count();
+            for &ptr in self.ephemeron_tables.borrow().iter() {
+                if cc.is_marked(ptr) {
+                    let table = erase(ptr);
+                    self.walk.borrow_mut().as_mut().unwrap().set_current(table);
+                    (*ptr.as_ref().value.get()).trace_ephemerons(cc);
+                }
+            }
+            if self.walk.borrow().as_ref().unwrap().visited_count() == visited_count {
+                break;
+            }
+        }
+
+        self.walk.take().unwrap()
+    }
+
+    // Must be called after the root object has been mutated.  The root is only traced once at the
+    // start of a cycle, so if marking is in progress it is traced again, otherwise any white
+    // objects newly stored in it could be freed while still reachable.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn root_barrier<R: Collect>(&self, root: &R) {
+        if self.phase.get() == CollectionPhase::Propagate {
+            let cc = CollectionContext { context: self };
+            root.trace(cc);
+        }
+    }
+
+    // Checks that no black object points to a white object, which can only happen if a write
+    // barrier was missed, in which case the white object could be freed while still reachable.
+    // Panics with the types of both objects if one is found.  Only performed while marking is in
+    // progress, and does nothing unless the "verify-barriers" feature is enabled.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    #[allow(unused)]
+    pub unsafe fn verify_barriers<R: Collect>(&self, root: &R) {
+        #[cfg(feature = "verify-barriers")]
+        if self.phase.get() == CollectionPhase::Propagate {
+            // The root is traced as soon as the cycle begins, so it is effectively black.
+            let cc = CollectionContext { context: self };
+            self.verifying.set(Some(core::any::type_name::<R>()));
+            root.trace(cc);
+
+            self.heap.for_each_object(|ptr| {
+                let header = ptr.as_ref();
+                if header.flags.color() == GcColor::Black && header.flags.needs_trace() {
+                    self.verifying.set(Some(GcBoxHeader::type_name(ptr)));
+                    GcBoxHeader::trace_value(ptr, cc);
+                }
+            });
+            self.verifying.set(None);
+        }
+    }
+
     // If the garbage collector is currently in the sleep phase, transition to the wake phase.
     pub fn wake(&self) {
-        if self.phase.get() == Phase::Sleep {
-            self.phase.set(Phase::Wake);
+        if self.phase.get() == CollectionPhase::Sleep {
+            self.set_phase(CollectionPhase::Wake);
         }
     }
 
@@ -143,12 +555,18 @@ impl Context {
     pub unsafe fn do_collection<R: Collect>(&self, root: &R, work: f64) -> f64 {
         let mut work_done = 0.0;
         let cc = CollectionContext { context: self };
+        #[cfg(feature = "std")]
+        self.phase_start.set(Some(Instant::now()));
 
         while work > work_done {
             match self.phase.get() {
-                Phase::Wake => {
+                CollectionPhase::Wake => {
                     // In the Wake phase, we trace the root object and add its children to the gray
-                    // queue, and transition to the propagate phase.
+                    // queue, and transition to the propagate phase.  A full cycle collects the
+                    // nursery along with everything else, so it is promoted first.
+                    self.notify(CollectionEvent::CycleStarted);
+                    self.promote_nursery();
+                    self.remembered_size.set(0);
                     root.trace(cc);
 
                     let root_size = mem::size_of::<R>() as f64;
@@ -156,16 +574,17 @@ impl Context {
                     self.allocation_debt
                         .set((self.allocation_debt.get() - root_size).max(0.0));
 
-                    self.phase.set(Phase::Propagate);
+                    self.resurrected.set(false);
+                    self.set_phase(CollectionPhase::Propagate);
                 }
-                Phase::Propagate => {
+                CollectionPhase::Propagate => {
                     // We look for an object first in the normal gray queue, then the "gray again"
                     // queue.  Objects from the normal gray queue count as regular work, but objects
                     // which are gray a second time have already been counted as work, so we don't
                     // double count them.  Processing "gray again" objects later also gives them
                     // more time to be mutated again without triggering another write barrier.
                     let next_gray = if let Some(ptr) = self.gray.borrow_mut().pop() {
-                        let gray_size = mem::size_of_val(ptr.as_ref()) as f64;
+                        let gray_size = GcBoxHeader::size(ptr) as f64;
                         work_done += gray_size;
                         self.allocation_debt
                             .set((self.allocation_debt.get() - gray_size).max(0.0));
@@ -179,175 +598,588 @@ impl Context {
                     if let Some(ptr) = next_gray {
                         // If we have an object in the gray queue, take one, trace it, and turn it
                         // black.
-                        let gc_box = ptr.as_ref();
-                        (*gc_box.value.get()).trace(cc);
-                        gc_box.flags.set_color(GcColor::Black);
+                        GcBoxHeader::trace_value(ptr, cc);
+                        ptr.as_ref().flags.set_color(GcColor::Black);
+                    } else if self.trace_ephemerons(cc) {
+                        // Tracing the values of ephemerons with marked keys marked new objects,
+                        // which may themselves be keys of other ephemerons, so we must keep
+                        // propagating until nothing new is marked.
+                    } else if self.resurrect(cc) {
+                        // Once every reachable object has been marked, any object registered for
+                        // finalization that is still white is unreachable.  Such objects are
+                        // resurrected into their finalization queues, which may make more objects
+                        // gray (including other queues), so we must keep propagating afterwards.
                     } else {
-                        // If we have no objects left in the normal gray queue, we enter the sweep
-                        // phase.
-                        self.phase.set(Phase::Sweep);
-                        self.sweep.set(self.all.get());
+                        // If we have no objects left in the normal gray queue, marking is
+                        // complete.  Ephemerons whose keys are unmarked are removed before any of
+                        // their keys can be freed, and we enter the sweep phase.
+                        self.verify_barriers(root);
+                        self.clear_ephemerons(cc);
+                        self.forget_unmarked_weak_collections(cc);
+                        self.forget_unmarked_finalization_queues(cc);
+                        self.set_phase(CollectionPhase::Sweep);
+                        self.heap.begin_sweep();
                     }
                 }
-                Phase::Sweep => {
-                    if let Some(sweep_ptr) = self.sweep.get() {
-                        let sweep = sweep_ptr.as_ref();
-                        let sweep_size = mem::size_of_val(sweep);
-
-                        let next_ptr = sweep.next.get();
-                        self.sweep.set(next_ptr);
-
-                        // If the next object in the sweep list is white, we need to remove it from
-                        // the main list and destruct it, otherwise it should be black, and we
-                        // simply turn it white again.
-                        if sweep.flags.color() == GcColor::White
-                            || sweep.flags.color() == GcColor::FreshWhite
-                        {
-                            if sweep.flags.has_weak_ref() {
-                                self.sweep_prev.set(Some(sweep_ptr));
-                                sweep.flags.set_has_weak_ref(false);
-                                if sweep.flags.alive() {
-                                    sweep.flags.set_alive(false);
-                                    // SAFETY: Since this object is white, that means there are no more strong pointers
-                                    // to this object, only weak pointers, so we can safely drop its contents.
-                                    core::ptr::drop_in_place(sweep.value.get());
-                                }
-                                continue;
-                            }
-                            // If the next object in the sweep portion of the main list is white, we
-                            // need to remove it from the main object list and destruct it.
-                            if let Some(sweep_prev) = self.sweep_prev.get() {
-                                sweep_prev.as_ref().next.set(next_ptr);
-                            } else {
-                                // If `sweep_prev` is None, then the sweep pointer is also the
-                                // beginning of the main object list, so we need to adjust it.
-                                debug_assert_eq!(self.all.get(), Some(sweep_ptr));
-                                self.all.set(next_ptr);
-                            }
-                            self.total_allocated
-                                .set(self.total_allocated.get() - sweep_size);
-                            work_done += sweep_size as f64;
-                            self.allocation_debt
-                                .set((self.allocation_debt.get() - sweep_size as f64).max(0.0));
-                            if sweep.flags.alive() {
-                                // If the alive flag is set, that means we havn't dropped the inner value of this object,
-                                // so it is safe to drop normally.
-                                Box::from_raw(sweep_ptr.as_ptr());
-                            } else {
-                                // If the alive flag is not set, then that means we have already dropped the inner value,
-                                // so we only need to free the GcBox.
-                                alloc::alloc::dealloc(
-                                    sweep_ptr.as_ptr().cast(),
-                                    Layout::for_value(sweep),
-                                );
+                CollectionPhase::Sweep => {
+                    // The heap is swept a page at a time.  Whether each object survives is decided
+                    // by the mark bits kept by its page rather than by its color, since objects
+                    // allocated into a page which has not been swept yet are marked to keep them
+                    // alive.
+                    let swept = self.heap.sweep_step(|ptr, marked| {
+                        let header = ptr.as_ref();
+
+                        // Quarantined objects have already been freed, and are only waiting for
+                        // their memory to be released.
+                        #[cfg(feature = "poison")]
+                        if header.flags.poisoned() {
+                            return;
+                        }
+
+                        let size = GcBoxHeader::size(ptr);
+                        if marked {
+                            // A marked object is kept, but turned back white.  No gray objects
+                            // should be left once marking is complete.
+                            debug_assert_ne!(header.flags.color(), GcColor::Gray);
+                            self.remembered_size.set(self.remembered_size.get() + size);
+                            self.update_cycle(|cycle| cycle.bytes_remembered += size);
+                            header.flags.set_has_weak_ref(false);
+                            header.flags.set_color(GcColor::White);
+                        } else if header.flags.has_weak_ref() {
+                            // An unmarked object which has weak pointers to it has its contents
+                            // dropped, but must be kept around until no weak pointers are left.
+                            header.flags.set_has_weak_ref(false);
+                            if header.flags.alive() {
+                                header.flags.set_alive(false);
+                                self.release_external_size(ptr);
+                                // SAFETY: Since this object is unmarked, that means there are no
+                                // more strong pointers to this object, only weak pointers, so we
+                                // can safely drop its contents.
+                                GcBoxHeader::drop_value(ptr);
                             }
                         } else {
-                            // If the next object in the sweep portion of the main list is black, we
-                            // need to keep it but turn it back white.  No gray objects should be in
-                            // this part of the main list, they should be added to the beginning of
-                            // the list before the sweep pointer, so it should not be possible for
-                            // us to encounter them here.
-                            debug_assert_eq!(sweep.flags.color(), GcColor::Black);
-                            self.sweep_prev.set(Some(sweep_ptr));
-                            self.remembered_size
-                                .set(self.remembered_size.get() + sweep_size);
-                            sweep.flags.set_has_weak_ref(false);
-                            sweep.flags.set_color(GcColor::White);
+                            work_done += size as f64;
+                            self.allocation_debt
+                                .set((self.allocation_debt.get() - size as f64).max(0.0));
+                            self.update_cycle(|cycle| {
+                                cycle.objects_freed += 1;
+                                cycle.bytes_freed += size;
+                            });
+                            self.free_unreachable(ptr, size);
                         }
-                    } else {
-                        // We are done sweeping, so enter the sleeping phase.
-                        self.sweep_prev.set(None);
-                        self.phase.set(Phase::Sleep);
+                    });
+
+                    if !swept {
+                        // We are done sweeping, so every dead object pointed to by a weak
+                        // collection has been dropped and may be pruned, and any page left empty
+                        // can be released.  Then we enter the sleeping phase.
+                        self.prune_weak_collections();
+                        #[cfg(feature = "poison")]
+                        self.release_quarantine();
+                        self.heap.release_empty_pages();
 
                         // Do not let debt accumulate across cycles, when we enter sleep, zero the debt out.
                         self.allocation_debt.set(0.0);
 
-                        let sleep = f64_to_usize(
-                            self.remembered_size.get() as f64 * self.parameters.pause_factor,
-                        )
-                        .min(self.parameters.min_sleep);
+                        // In stress mode, the next cycle begins with the very next allocation.
+                        let sleep = if self.parameters.stress {
+                            0
+                        } else {
+                            f64_to_usize(
+                                self.remembered_size.get() as f64 * self.parameters.pause_factor,
+                            )
+                            .min(self.parameters.min_sleep)
+                        };
 
                         self.wakeup_total.set(self.total_allocated.get() + sleep);
+
+                        self.set_phase(CollectionPhase::Sleep);
+                        self.last_cycle.set(self.cycle.take());
+                        self.cycles.set(self.cycles.get() + 1);
+                        self.notify(CollectionEvent::CycleCompleted);
                     }
                 }
-                Phase::Sleep => break,
+                CollectionPhase::Sleep => break,
             }
         }
 
+        #[cfg(feature = "std")]
+        self.record_phase_time();
+        #[cfg(feature = "std")]
+        self.phase_start.set(None);
+
         work_done
     }
 
-    unsafe fn allocate<T: Collect>(&self, t: T) -> NonNull<GcBox<T>> {
-        let alloc_size = mem::size_of::<GcBox<T>>();
-        self.total_allocated
-            .set(self.total_allocated.get() + alloc_size);
-        if self.phase.get() == Phase::Sleep && self.total_allocated.get() > self.wakeup_total.get()
-        {
-            self.phase.set(Phase::Wake);
+    // Moves to the given phase, ending the current one.
+    fn set_phase(&self, phase: CollectionPhase) {
+        #[cfg(feature = "std")]
+        self.record_phase_time();
+        let from = self.phase.replace(phase);
+        self.notify(CollectionEvent::PhaseChanged { from, to: phase });
+    }
+
+    fn notify(&self, event: CollectionEvent) {
+        if let Some(hook) = &self.parameters.collection_hook {
+            hook.call(event, &self.stats());
         }
+    }
 
-        if self.phase.get() != Phase::Sleep {
-            self.allocation_debt.set(
-                self.allocation_debt.get()
-                    + alloc_size as f64
-                    + alloc_size as f64 / self.parameters.timing_factor,
-            );
+    // Adds the time since `phase_start` to the current cycle's time for the current phase.
+    #[cfg(feature = "std")]
+    fn record_phase_time(&self) {
+        if let Some(start) = self.phase_start.get() {
+            let now = Instant::now();
+            self.phase_start.set(Some(now));
+            self.update_cycle(|cycle| {
+                let time = match self.phase.get() {
+                    CollectionPhase::Wake => &mut cycle.wake_time,
+                    CollectionPhase::Propagate => &mut cycle.propagate_time,
+                    CollectionPhase::Sweep => &mut cycle.sweep_time,
+                    CollectionPhase::Sleep => return,
+                };
+                *time += now - start;
+            });
         }
+    }
 
-        let flags = GcFlags::new();
-        flags.set_alive(true);
-        flags.set_needs_trace(T::needs_trace());
+    fn update_cycle(&self, f: impl FnOnce(&mut CycleStats)) {
+        let mut cycle = self.cycle.get();
+        f(&mut cycle);
+        self.cycle.set(cycle);
+    }
+
+    // If generational collection is enabled, the collector is sleeping, and the nursery has grown
+    // larger than `ArenaParameters::nursery_size`, performs a minor collection.  Every object in
+    // the nursery which is reachable from either the root or the remembered set is promoted out of
+    // it, and the rest are freed.
+    //
+    // Minor collections are not incremental, but only ever trace and sweep objects in the nursery.
+    //
+    // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
+    // reachable from the given root object.
+    pub unsafe fn collect_nursery<R: Collect>(&self, root: &R) {
+        let nursery_full = match self.parameters.nursery_size {
+            Some(nursery_size) => self.nursery_allocated.get() > nursery_size,
+            None => false,
+        };
+        if self.phase.get() != CollectionPhase::Sleep || !nursery_full {
+            return;
+        }
+
+        let cc = CollectionContext { context: self };
+        self.minor.set(true);
+
+        // While `minor` is set, tracing ignores every old object, so only young objects reachable
+        // from the root or from the remembered set are marked.
+        root.trace(cc);
+        for ptr in self.remembered_set.borrow_mut().drain(..) {
+            let gc_box = ptr.as_ref();
+            gc_box.flags.set_remembered(false);
+            if gc_box.flags.needs_trace() {
+                GcBoxHeader::trace_value(ptr, cc);
+            }
+        }
+
+        // Marking proceeds exactly as in the propagate phase of a full cycle, except that it is
+        // done all at once.
+        loop {
+            while let Some(ptr) = self.gray.borrow_mut().pop() {
+                GcBoxHeader::trace_value(ptr, cc);
+                ptr.as_ref().flags.set_color(GcColor::Black);
+            }
+
+            if !self.trace_ephemerons(cc) && !self.resurrect(cc) {
+                break;
+            }
+        }
+        self.clear_ephemerons(cc);
+        self.forget_unmarked_weak_collections(cc);
+        self.forget_unmarked_finalization_queues(cc);
+
+        let nursery = mem::take(&mut *self.nursery.borrow_mut());
+        for ptr in nursery {
+            let gc_box = ptr.as_ref();
+            let marked = gc_box.flags.color() == GcColor::Black;
+            if marked || gc_box.flags.has_weak_ref() {
+                if marked {
+                    let (allocation, layout) = GcBoxHeader::allocation(ptr);
+                    self.heap.set_marked(allocation, layout, false);
+                } else {
+                    // Just like in the sweep phase, an unreachable object which has weak pointers
+                    // to it has its contents dropped but must be kept around until no weak
+                    // pointers are left, which only a full cycle can determine.
+                    gc_box.flags.set_alive(false);
+                    self.release_external_size(ptr);
+                    GcBoxHeader::drop_value(ptr);
+                }
+                gc_box.flags.set_young(false);
+                gc_box.flags.set_has_weak_ref(false);
+                gc_box.flags.set_color(GcColor::White);
+            } else {
+                self.free_unreachable(ptr, GcBoxHeader::size(ptr));
+            }
+        }
+        self.nursery_allocated.set(0);
+
+        self.prune_weak_collections();
+        self.heap.release_empty_pages();
+        self.minor.set(false);
+        self.minor_collections.set(self.minor_collections.get() + 1);
+        self.notify(CollectionEvent::MinorCollectionCompleted);
+    }
+
+    unsafe fn allocate<T: Collect>(&self, t: T) -> NonNull<GcBox<T>> {
+        if let Err(error) = self.check_heap_limit(mem::size_of::<GcBox<T>>()) {
+            panic!("{}", error);
+        }
+        self.allocate_unchecked(t)
+    }
+
+    unsafe fn try_allocate<T: Collect>(
+        &self,
+        t: T,
+    ) -> Result<NonNull<GcBox<T>>, HeapLimitExceeded> {
+        self.check_heap_limit(mem::size_of::<GcBox<T>>())?;
+        Ok(self.allocate_unchecked(t))
+    }
+
+    unsafe fn allocate_unchecked<T: Collect>(&self, t: T) -> NonNull<GcBox<T>> {
+        let layout = Layout::new::<GcBox<T>>();
+        let flags = self.new_object_flags(layout.size(), T::needs_trace());
 
         // Make the generated code easier to optimize into `T` being constructed in place or at the
         // very least only memcpy'd once.
         // For more information, see: https://github.com/kyren/gc-arena/pull/14
-        /*
-        let ptr = NonNull::new_unchecked(Box::into_raw(Box::new(GcBox {
-            flags: flags,
-            next: Cell::new(self.all.get()),
-            value: UnsafeCell::new(t),
-        })));
-        */
-        let mut uninitialized = Box::new(mem::MaybeUninit::<GcBox<T>>::uninit());
+        let ptr = self.heap_allocate(layout, 0).cast::<GcBox<T>>();
         core::ptr::write(
-            uninitialized.as_mut_ptr(),
+            ptr.as_ptr(),
             GcBox {
-                flags: flags,
-                next: Cell::new(self.all.get()),
+                header: GcBoxHeader::new::<T>(flags),
                 value: UnsafeCell::new(t),
             },
         );
-        let ptr = NonNull::new_unchecked(Box::into_raw(uninitialized) as *mut GcBox<T>);
 
-        self.all.set(Some(static_gc_box(ptr)));
-        if self.phase.get() == Phase::Sweep && self.sweep_prev.get().is_none() {
-            self.sweep_prev.set(self.all.get());
+        self.link_object(erase(ptr), layout.size());
+        ptr
+    }
+
+    unsafe fn allocate_slice<T: Collect>(&self, items: Vec<T>) -> NonNull<GcBox<[T]>> {
+        match self.try_allocate_slice(items) {
+            Ok(ptr) => ptr,
+            Err(error) => panic!("{}", error),
         }
+    }
 
-        ptr
+    unsafe fn try_allocate_slice<T: Collect>(
+        &self,
+        items: Vec<T>,
+    ) -> Result<NonNull<GcBox<[T]>>, HeapLimitExceeded> {
+        self.try_allocate_slice_with(items, GcBoxHeader::new_slice::<T>)
+    }
+
+    unsafe fn allocate_str(&self, s: &str) -> NonNull<GcBox<str>> {
+        match self.try_allocate_str(s) {
+            Ok(ptr) => ptr,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    unsafe fn try_allocate_str(&self, s: &str) -> Result<NonNull<GcBox<str>>, HeapLimitExceeded> {
+        let ptr = self.try_allocate_slice_with(s.as_bytes().to_vec(), GcBoxHeader::new_str)?;
+        // A `str` has the same layout as a `[u8]`, and the bytes are valid UTF-8.
+        Ok(NonNull::new_unchecked(ptr.as_ptr() as *mut GcBox<str>))
+    }
+
+    unsafe fn try_allocate_slice_with<T: Collect>(
+        &self,
+        mut items: Vec<T>,
+        new_header: fn(GcFlags) -> GcBoxHeader,
+    ) -> Result<NonNull<GcBox<[T]>>, HeapLimitExceeded> {
+        let len = items.len();
+        let (layout, offset) = slice_allocation_layout::<T>(len);
+        self.check_heap_limit(layout.size())?;
+        let flags = self.new_object_flags(layout.size(), T::needs_trace());
+
+        let allocation = self.heap_allocate(layout, offset);
+        let header = allocation.as_ptr().add(offset);
+        (header as *mut usize).sub(1).write(len);
+        let ptr = slice_box_ptr::<T>(NonNull::new_unchecked(header), len);
+        core::ptr::addr_of_mut!((*ptr.as_ptr()).header).write(new_header(flags));
+        core::ptr::copy_nonoverlapping(
+            items.as_ptr(),
+            core::ptr::addr_of_mut!((*ptr.as_ptr()).value) as *mut T,
+            len,
+        );
+        // The items have been moved into the new allocation.
+        items.set_len(0);
+
+        self.link_object(erase(ptr), layout.size());
+        Ok(ptr)
+    }
+
+    // Accounts for a new object of the given size, possibly waking the collector, and returns the
+    // flags the new object should start with.
+    fn new_object_flags(&self, alloc_size: usize, needs_trace: bool) -> GcFlags {
+        self.charge_allocation(alloc_size);
+
+        let flags = GcFlags::new();
+        flags.set_alive(true);
+        flags.set_needs_trace(needs_trace);
+        flags.set_young(
+            self.parameters.nursery_size.is_some() && self.phase.get() == CollectionPhase::Sleep,
+        );
+        flags
+    }
+
+    // Refuses an allocation of the given size if it would exceed the maximum heap size, in which
+    // case the host is notified and an emergency collection is scheduled.
+    fn check_heap_limit(&self, size: usize) -> Result<(), HeapLimitExceeded> {
+        if let Some(max_heap_size) = self.parameters.max_heap_size {
+            let total_allocated = self.total_allocated.get();
+            if total_allocated.saturating_add(size) > max_heap_size {
+                let error = HeapLimitExceeded {
+                    requested: size,
+                    total_allocated,
+                    max_heap_size,
+                };
+                self.emergency_collection.set(true);
+                if let Some(hook) = &self.parameters.heap_pressure_hook {
+                    hook.call(&error);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    // Adds newly allocated memory to `total_allocated`, waking the collector if enough has been
+    // allocated, and charges allocation debt for it if the collector is awake.
+    fn charge_allocation(&self, size: usize) {
+        self.total_allocated.set(self.total_allocated.get() + size);
+        if self.phase.get() == CollectionPhase::Sleep
+            && self.total_allocated.get() > self.wakeup_total.get()
+        {
+            self.set_phase(CollectionPhase::Wake);
+        }
+
+        if self.phase.get() != CollectionPhase::Sleep {
+            self.allocation_debt.set(
+                self.allocation_debt.get()
+                    + size as f64
+                    + size as f64 / self.parameters.timing_factor,
+            );
+        }
+    }
+
+    unsafe fn set_external_size(&self, ptr: NonNull<GcBoxHeader>, size: usize) {
+        debug_assert!(ptr.as_ref().flags.alive());
+        let old_size = if size == 0 {
+            self.external_sizes.borrow_mut().remove(&ptr)
+        } else {
+            self.external_sizes.borrow_mut().insert(ptr, size)
+        }
+        .unwrap_or(0);
+
+        if size > old_size {
+            self.external_allocated
+                .set(self.external_allocated.get() + (size - old_size));
+            self.charge_allocation(size - old_size);
+        } else {
+            self.external_allocated
+                .set(self.external_allocated.get() - (old_size - size));
+            self.total_allocated
+                .set(self.total_allocated.get() - (old_size - size));
+        }
+    }
+
+    fn external_size(&self, ptr: NonNull<GcBoxHeader>) -> usize {
+        self.external_sizes.borrow().get(&ptr).copied().unwrap_or(0)
+    }
+
+    // Releases any external size reported for an object whose value is about to be dropped by the
+    // collector.
+    fn release_external_size(&self, ptr: NonNull<GcBoxHeader>) {
+        let mut external_sizes = self.external_sizes.borrow_mut();
+        if external_sizes.is_empty() {
+            return;
+        }
+        if let Some(size) = external_sizes.remove(&ptr) {
+            self.external_allocated
+                .set(self.external_allocated.get() - size);
+            self.total_allocated.set(self.total_allocated.get() - size);
+        }
+    }
+
+    fn heap_allocate(&self, layout: Layout, header_offset: usize) -> NonNull<u8> {
+        match self.heap.allocate(layout, header_offset) {
+            Some(ptr) => ptr,
+            None => alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+
+    // Accounts for a newly allocated object, and adds it to the nursery if it is young.
+    unsafe fn link_object(&self, ptr: NonNull<GcBoxHeader>, alloc_size: usize) {
+        self.object_count.set(self.object_count.get() + 1);
+        #[cfg(feature = "profiling")]
+        {
+            let type_name = GcBoxHeader::type_name(ptr);
+            let mut type_stats = self.type_stats.borrow_mut();
+            let stats = type_stats.entry(type_name).or_insert(TypeStats {
+                type_name,
+                live_count: 0,
+                live_bytes: 0,
+                allocated_count: 0,
+                allocated_bytes: 0,
+            });
+            stats.live_count += 1;
+            stats.live_bytes += alloc_size;
+            stats.allocated_count += 1;
+            stats.allocated_bytes += alloc_size as u64;
+        }
+        if ptr.as_ref().flags.young() {
+            self.nursery.borrow_mut().push(ptr);
+            self.nursery_allocated
+                .set(self.nursery_allocated.get() + alloc_size);
+        }
+    }
+
+    // Drops the contents of the given object, unless they have already been dropped because the
+    // object was only being kept around for weak pointers, and returns its memory to the allocator.
+    unsafe fn free(&self, ptr: NonNull<GcBoxHeader>) {
+        let (allocation, layout) = GcBoxHeader::allocation(ptr);
+        if ptr.as_ref().flags.alive() {
+            GcBoxHeader::drop_value(ptr);
+        }
+        self.heap.deallocate(allocation, layout);
+        self.object_count.set(self.object_count.get() - 1);
+    }
+
+    // Frees an object found to be unreachable.
+    unsafe fn free_unreachable(&self, ptr: NonNull<GcBoxHeader>, size: usize) {
+        self.release_external_size(ptr);
+        self.total_allocated.set(self.total_allocated.get() - size);
+        self.objects_freed.set(self.objects_freed.get() + 1);
+        self.bytes_freed.set(self.bytes_freed.get() + size as u64);
+        #[cfg(feature = "profiling")]
+        {
+            let mut type_stats = self.type_stats.borrow_mut();
+            let stats = type_stats.get_mut(GcBoxHeader::type_name(ptr)).unwrap();
+            stats.live_count -= 1;
+            stats.live_bytes -= size;
+        }
+        #[cfg(feature = "poison")]
+        if self.parameters.quarantine_cycles != 0 {
+            self.quarantine(ptr);
+            return;
+        }
+        self.free(ptr);
     }
 
-    unsafe fn write_barrier<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
+    // Drops the contents of an unreachable object and overwrites them with a recognizable pattern,
+    // but keeps its memory for a number of cycles rather than freeing it, so that any remaining
+    // pointer to it panics when used rather than reading reused memory.
+    #[cfg(feature = "poison")]
+    unsafe fn quarantine(&self, ptr: NonNull<GcBoxHeader>) {
+        let header = ptr.as_ref();
+        if header.flags.alive() {
+            header.flags.set_alive(false);
+            GcBoxHeader::drop_value(ptr);
+        }
+        let (allocation, layout) = GcBoxHeader::allocation(ptr);
+        let value = GcBoxHeader::value_ptr(ptr) as *mut u8;
+        let end = allocation.as_ptr().add(layout.size());
+        core::ptr::write_bytes(value, POISON, end.offset_from(value) as usize);
+        header.flags.set_poisoned(true);
+
+        self.object_count.set(self.object_count.get() - 1);
+        self.quarantine
+            .borrow_mut()
+            .push_back((ptr, self.cycles.get()));
+    }
+
+    // Called as a cycle completes, returns the memory of every object which was freed at least
+    // `quarantine_cycles` cycles before this one to the allocator.
+    #[cfg(feature = "poison")]
+    unsafe fn release_quarantine(&self) {
+        let mut quarantine = self.quarantine.borrow_mut();
+        while let Some(&(ptr, freed_at)) = quarantine.front() {
+            if self.cycles.get() - freed_at < self.parameters.quarantine_cycles {
+                break;
+            }
+            quarantine.pop_front();
+            let (allocation, layout) = GcBoxHeader::allocation(ptr);
+            self.heap.deallocate(allocation, layout);
+        }
+    }
+
+    unsafe fn write_barrier<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) {
         // During the propagating phase, if we are mutating a black object, we may add a white
         // object to it and invalidate the invariant that black objects may not point to white
         // objects.  Turn black obejcts to gray to prevent this.
-        let gc_box = ptr.as_ref();
-        if self.phase.get() == Phase::Propagate && gc_box.flags.color() == GcColor::Black {
+        self.write_barriers.set(self.write_barriers.get() + 1);
+        let gc_box = erase(ptr).as_ref();
+        if self.phase.get() == CollectionPhase::Propagate && gc_box.flags.color() == GcColor::Black
+        {
+            self.update_cycle(|cycle| cycle.gray_again += 1);
             gc_box.flags.set_color(GcColor::Gray);
-            self.gray_again.borrow_mut().push(static_gc_box(ptr));
+            self.gray_again.borrow_mut().push(erase(ptr));
+        }
+
+        // While sleeping with generational collection enabled, a mutated old object may now point
+        // to a young object, so it must be treated as a root during the next minor collection.
+        if self.parameters.nursery_size.is_some()
+            && self.phase.get() == CollectionPhase::Sleep
+            && !gc_box.flags.young()
+            && !gc_box.flags.remembered()
+        {
+            gc_box.flags.set_remembered(true);
+            self.remembered_set.borrow_mut().push(erase(ptr));
         }
     }
 
-    unsafe fn trace<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
-        let gc_box = ptr.as_ref();
+    unsafe fn trace<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) {
+        #[cfg(feature = "verify-barriers")]
+        if let Some(parent) = self.verifying.get() {
+            if matches!(
+                erase(ptr).as_ref().flags.color(),
+                GcColor::White | GcColor::FreshWhite
+            ) {
+                self.verifying.set(None);
+                panic!(
+                    "missing write barrier: black object of type `{}` points to white object of type `{}`",
+                    parent,
+                    GcBoxHeader::type_name(erase(ptr)),
+                );
+            }
+            return;
+        }
+        if let Some(walk) = &mut *self.walk.borrow_mut() {
+            walk.trace(erase(ptr));
+            return;
+        }
+        let gc_box = erase(ptr).as_ref();
+        if self.minor.get() && !gc_box.flags.young() {
+            return;
+        }
         match gc_box.flags.color() {
             GcColor::Black | GcColor::Gray => {}
             GcColor::White | GcColor::FreshWhite => {
+                let (allocation, layout) = GcBoxHeader::allocation(erase(ptr));
+                self.heap.set_marked(allocation, layout, true);
+                self.mark_count.set(self.mark_count.get() + 1);
+                if !self.minor.get() {
+                    let size = layout.size();
+                    self.update_cycle(|cycle| {
+                        cycle.objects_marked += 1;
+                        cycle.bytes_marked += size;
+                    });
+                }
                 if gc_box.flags.needs_trace() {
                     // A white traceable object is not in the gray queue, becomes gray and enters
                     // the normal gray queue.
                     gc_box.flags.set_color(GcColor::Gray);
-                    self.gray.borrow_mut().push(static_gc_box(ptr));
+                    self.gray.borrow_mut().push(erase(ptr));
                 } else {
                     // A white object that doesn't need tracing simply becomes black.
                     gc_box.flags.set_color(GcColor::Black);
@@ -356,42 +1188,219 @@ impl Context {
         }
     }
 
+    // Traces the values of every ephemeron with a marked key in every reachable table, and returns
+    // true if this caused any new object to be marked.
+    unsafe fn trace_ephemerons(&self, cc: CollectionContext) -> bool {
+        let mark_count = self.mark_count.get();
+        for &ptr in self.ephemeron_tables.borrow().iter() {
+            if cc.is_marked(ptr) {
+                (*ptr.as_ref().value.get()).trace_ephemerons(cc);
+            }
+        }
+        self.mark_count.get() != mark_count
+    }
+
+    unsafe fn clear_ephemerons(&self, cc: CollectionContext) {
+        self.ephemeron_tables.borrow_mut().retain(|&ptr| {
+            if cc.is_marked(ptr) {
+                (*ptr.as_ref().value.get()).clear_dead(cc);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    unsafe fn add_ephemeron_table<T: TraceEphemerons>(&self, ptr: NonNull<GcBox<T>>) {
+        self.ephemeron_tables
+            .borrow_mut()
+            .push(static_ephemeron_box(ptr));
+    }
+
+    unsafe fn add_weak_collection<T: PruneDead>(&self, ptr: NonNull<GcBox<T>>) {
+        self.weak_collections
+            .borrow_mut()
+            .push(static_weak_collection_box(ptr));
+    }
+
+    // Weak collections which are unmarked at the end of marking are about to be freed, so they must
+    // be removed before sweeping.
+    unsafe fn forget_unmarked_weak_collections(&self, cc: CollectionContext) {
+        self.weak_collections
+            .borrow_mut()
+            .retain(|&ptr| cc.is_marked(ptr));
+    }
+
+    unsafe fn prune_weak_collections(&self) {
+        for &ptr in self.weak_collections.borrow().iter() {
+            (*ptr.as_ref().value.get()).prune_dead();
+        }
+    }
+
+    unsafe fn trace_weak<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) {
+        #[cfg(feature = "verify-barriers")]
+        if self.verifying.get().is_some() {
+            return;
+        }
+        if let Some(walk) = &mut *self.walk.borrow_mut() {
+            walk.trace_weak(erase(ptr));
+            return;
+        }
+        let gc_box = erase(ptr).as_ref();
+        if self.minor.get() && !gc_box.flags.young() {
+            return;
+        }
+        gc_box.flags.set_has_weak_ref(true);
+        if gc_box.flags.color() == GcColor::FreshWhite {
+            gc_box.flags.set_color(GcColor::White);
+        }
+    }
+
+    unsafe fn is_marked<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) -> bool {
+        if let Some(walk) = &*self.walk.borrow() {
+            return walk.is_visited(erase(ptr));
+        }
+        let gc_box = erase(ptr).as_ref();
+        if self.minor.get() && !gc_box.flags.young() {
+            // During a minor collection, every old object is considered live.
+            return true;
+        }
+        match gc_box.flags.color() {
+            GcColor::Gray | GcColor::Black => true,
+            GcColor::White | GcColor::FreshWhite => false,
+        }
+    }
+
+    // Promotes every object in the nursery, and empties the remembered set.
+    unsafe fn promote_nursery(&self) {
+        for ptr in self.nursery.borrow_mut().drain(..) {
+            ptr.as_ref().flags.set_young(false);
+        }
+        self.nursery_allocated.set(0);
+
+        for ptr in self.remembered_set.borrow_mut().drain(..) {
+            ptr.as_ref().flags.set_remembered(false);
+        }
+    }
+
+    // Gives every marked queue which has not yet done so this cycle the chance to resurrect its
+    // unreachable objects, returning false if there were no such queues.
+    //
+    // Unmarked queues are skipped rather than forgotten, since they may still be reachable from an
+    // object resurrected by another queue, and so must be given their own chance once they are
+    // marked.
+    unsafe fn resurrect(&self, cc: CollectionContext) -> bool {
+        self.resurrected.set(true);
+        let mut queues = self.finalization_queues.borrow_mut();
+        let start = self.resurrected_queues.get();
+        let mut resurrected = start;
+        for i in start..queues.len() {
+            if cc.is_marked(queues[i]) {
+                queues.swap(resurrected, i);
+                (*queues[resurrected].as_ref().value.get()).resurrect(cc);
+                resurrected += 1;
+            }
+        }
+        self.resurrected_queues.set(resurrected);
+        resurrected != start
+    }
+
+    // Queues which are unmarked once marking is complete are about to be freed, along with any of
+    // their registered objects, so they must be removed before sweeping.
+    unsafe fn forget_unmarked_finalization_queues(&self, cc: CollectionContext) {
+        self.finalization_queues
+            .borrow_mut()
+            .retain(|&ptr| cc.is_marked(ptr));
+        self.resurrected_queues.set(0);
+    }
+
+    unsafe fn add_finalization_queue<T: Resurrect>(&self, ptr: NonNull<GcBox<T>>) {
+        self.finalization_barrier(ptr);
+        self.finalization_queues
+            .borrow_mut()
+            .push(static_resurrect_box(ptr));
+    }
+
+    // Queues and registered objects are not traced normally, so they must not be swept without
+    // first being given the chance to resurrect.  If finalization has already happened for this
+    // cycle, then the only safe thing to do is to keep the object alive until the next cycle.
+    unsafe fn finalization_barrier<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) {
+        if self.phase.get() == CollectionPhase::Propagate && self.resurrected.get() {
+            self.trace(ptr);
+        }
+    }
+
     /// Determines whether or not a Gc pointer is safe to be upgraded.
     /// This is used by weak pointers to determine if it can safely upgrade to a strong pointer.
     ///
     /// Safety: `ptr` must be a valid pointer to a GcBox<T>.
-    unsafe fn upgrade<T: Collect>(&self, ptr: NonNull<GcBox<T>>) -> bool {
-        let gc_box = ptr.as_ref();
+    unsafe fn upgrade<T: Collect + ?Sized>(&self, ptr: NonNull<GcBox<T>>) -> bool {
+        #[cfg(feature = "poison")]
+        GcBoxHeader::assert_not_poisoned(erase(ptr));
+        let gc_box = erase(ptr).as_ref();
 
         // This object has already been freed, definitely not safe to upgrade.
         if !gc_box.flags.alive() {
             return false;
         }
 
-        // If we are in the sweep phase, the color is white, and we are not freshly allocated, then that means this object will
-        // be swept soon, so we cannot upgrade.
-        if self.phase.get() == Phase::Sweep && gc_box.flags.color() == GcColor::White {
-            return false;
+        // If we are in the sweep phase and this object was not marked, then unless its page has
+        // already been swept, this object will be swept soon, so we cannot upgrade.
+        if self.phase.get() == CollectionPhase::Sweep {
+            let (allocation, layout) = GcBoxHeader::allocation(erase(ptr));
+            return !self.heap.is_doomed(allocation, layout);
         }
         true
     }
 }
 
+/// The phases of a garbage collection cycle, reported to the hook set with
+/// `ArenaParameters::set_collection_hook`.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum Phase {
+pub enum CollectionPhase {
+    /// The cycle is about to begin, and will start by tracing the root.
     Wake,
+    /// Every object reachable from the root is being marked.
     Propagate,
+    /// Every object which was not marked is being freed.
     Sweep,
+    /// The cycle is complete, and the collector is waiting for enough allocation to begin the
+    /// next one.
     Sleep,
 }
 
+// Every object begins with its header, so a pointer to any `GcBox` is also a pointer to its header,
+// which erases both the type and the 'gc lifetime.
+#[inline]
+fn erase<T: Collect + ?Sized>(ptr: NonNull<GcBox<T>>) -> NonNull<GcBoxHeader> {
+    ptr.cast()
+}
+
 #[inline]
-unsafe fn static_gc_box<'gc>(
-    ptr: NonNull<GcBox<dyn Collect + 'gc>>,
-) -> NonNull<GcBox<dyn Collect>> {
+unsafe fn static_resurrect_box<'gc>(
+    ptr: NonNull<GcBox<dyn Resurrect + 'gc>>,
+) -> NonNull<GcBox<dyn Resurrect>> {
     mem::transmute(ptr)
 }
 
+#[inline]
+unsafe fn static_ephemeron_box<'gc>(
+    ptr: NonNull<GcBox<dyn TraceEphemerons + 'gc>>,
+) -> NonNull<GcBox<dyn TraceEphemerons>> {
+    mem::transmute(ptr)
+}
+
+#[inline]
+unsafe fn static_weak_collection_box<'gc>(
+    ptr: NonNull<GcBox<dyn PruneDead + 'gc>>,
+) -> NonNull<GcBox<dyn PruneDead>> {
+    mem::transmute(ptr)
+}
+
+// Every byte of a quarantined object after its header is overwritten with this.
+#[cfg(feature = "poison")]
+const POISON: u8 = 0xdd;
+
 /// Rounds a floating point number to an unsigned integer.
 ///
 /// If the floating point number is outside the bounds of the unsigned
@@ -0,0 +1,182 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::snapshot::HeapSnapshot;
+
+/// The dominator tree of a `HeapSnapshot`, returned by `HeapSnapshot::dominators`.
+///
+/// One node dominates another if every path of strong edges from the root to the second node goes
+/// through the first, so that if the first node became unreachable, the second would too.  The
+/// retained size of a node is the total size of every node it dominates, including itself, which
+/// is the amount of memory that would be freed if it were no longer referenced.
+#[derive(Clone, Debug)]
+pub struct DominatorTree {
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    retained: Vec<usize>,
+}
+
+/// The combined sizes of every object of a single type, see `DominatorTree::type_summaries`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TypeSummary {
+    pub type_name: &'static str,
+    /// The number of objects of this type.
+    pub count: usize,
+    /// The total size of every object of this type.
+    pub shallow_size: usize,
+    /// The total size of everything retained by objects of this type.  Objects dominated by
+    /// another object of the same type are only counted once.
+    pub retained_size: usize,
+}
+
+impl DominatorTree {
+    pub(crate) fn new(snapshot: &HeapSnapshot) -> DominatorTree {
+        let len = snapshot.nodes().len();
+        let mut successors = vec![Vec::new(); len];
+        let mut predecessors = vec![Vec::new(); len];
+        for edge in snapshot.edges().iter().filter(|edge| !edge.weak) {
+            successors[edge.from].push(edge.to);
+            predecessors[edge.to].push(edge.from);
+        }
+
+        // Number every node reachable from the root in postorder, using an explicit stack since
+        // object graphs may be very deep.
+        let mut postorder = Vec::with_capacity(len);
+        let mut order = vec![None; len];
+        let mut visited = vec![false; len];
+        let mut stack = vec![(HeapSnapshot::ROOT, 0)];
+        visited[HeapSnapshot::ROOT] = true;
+        while let Some((node, next_child)) = stack.last_mut() {
+            if let Some(&child) = successors[*node].get(*next_child) {
+                *next_child += 1;
+                if !visited[child] {
+                    visited[child] = true;
+                    stack.push((child, 0));
+                }
+            } else {
+                order[*node] = Some(postorder.len());
+                postorder.push(*node);
+                stack.pop();
+            }
+        }
+
+        // "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy: nodes are visited in
+        // reverse postorder until every immediate dominator has settled.
+        let mut idom = vec![None; len];
+        idom[HeapSnapshot::ROOT] = Some(HeapSnapshot::ROOT);
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while order[a] < order[b] {
+                    a = idom[a].unwrap();
+                }
+                while order[b] < order[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in postorder.iter().rev().skip(1) {
+                let mut new_idom = None;
+                for &pred in &predecessors[node] {
+                    if idom[pred].is_some() {
+                        new_idom = Some(match new_idom {
+                            Some(current) => intersect(&idom, pred, current),
+                            None => pred,
+                        });
+                    }
+                }
+                if new_idom != idom[node] {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[HeapSnapshot::ROOT] = None;
+
+        // A node's dominator always comes before it in reverse postorder, so retained sizes can be
+        // summed up the tree in postorder.
+        let mut children = vec![Vec::new(); len];
+        let mut retained: Vec<usize> = snapshot.nodes().iter().map(|node| node.size).collect();
+        for &node in &postorder {
+            if let Some(parent) = idom[node] {
+                children[parent].push(node);
+                retained[parent] += retained[node];
+            }
+        }
+        for (node, retained) in retained.iter_mut().enumerate() {
+            if order[node].is_none() {
+                *retained = 0;
+            }
+        }
+
+        DominatorTree {
+            idom,
+            children,
+            retained,
+        }
+    }
+
+    /// The immediate dominator of the given node, or `None` for the root and for any node which
+    /// is only reachable through weak edges.
+    pub fn immediate_dominator(&self, node: usize) -> Option<usize> {
+        self.idom[node]
+    }
+
+    /// Every node whose immediate dominator is the given node.
+    pub fn dominated(&self, node: usize) -> &[usize] {
+        &self.children[node]
+    }
+
+    /// The total size of the given node and every node it dominates.
+    pub fn retained_size(&self, node: usize) -> usize {
+        self.retained[node]
+    }
+
+    /// Summarizes the sizes of every object in the snapshot by type, sorted by retained size from
+    /// largest to smallest.  The snapshot must be the one this tree was created from.
+    pub fn type_summaries(&self, snapshot: &HeapSnapshot) -> Vec<TypeSummary> {
+        let nodes = snapshot.nodes();
+        let mut summaries: BTreeMap<&'static str, TypeSummary> = BTreeMap::new();
+
+        // Walk the dominator tree while counting how many ancestors of the current node have each
+        // type, so that only the outermost objects of a type add their retained size.
+        let mut ancestors: BTreeMap<&'static str, usize> = BTreeMap::new();
+        let mut stack = vec![(HeapSnapshot::ROOT, false)];
+        while let Some((node, exiting)) = stack.pop() {
+            let type_name = nodes[node].type_name;
+            if exiting {
+                *ancestors.get_mut(type_name).unwrap() -= 1;
+                continue;
+            }
+
+            if node != HeapSnapshot::ROOT {
+                let summary = summaries.entry(type_name).or_insert(TypeSummary {
+                    type_name,
+                    count: 0,
+                    shallow_size: 0,
+                    retained_size: 0,
+                });
+                summary.count += 1;
+                summary.shallow_size += nodes[node].size;
+                if ancestors.get(type_name).copied().unwrap_or(0) == 0 {
+                    summary.retained_size += self.retained[node];
+                }
+            }
+
+            *ancestors.entry(type_name).or_insert(0) += 1;
+            stack.push((node, true));
+            for &child in &self.children[node] {
+                stack.push((child, false));
+            }
+        }
+
+        let mut summaries: Vec<TypeSummary> = summaries.into_values().collect();
+        summaries.sort_by_key(|summary| Reverse(summary.retained_size));
+        summaries
+    }
+}
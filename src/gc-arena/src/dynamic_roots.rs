@@ -0,0 +1,192 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{self, Debug};
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::rootable::{Root, Rootable};
+use crate::types::GcBox;
+
+/// A set of objects which are kept alive by `'static` handles, so that code outside of the arena
+/// can hold onto objects between calls to `mutate`.
+///
+/// The set itself must be stored somewhere reachable from the root of the arena.  Objects are
+/// added to the set with `stash`, which returns a `DynamicRoot` handle, and every object with a
+/// live handle is kept alive by the set.  Inside a later call to `mutate`, a handle may be turned
+/// back into a `Gc` pointer with `fetch`.
+pub struct DynamicRootSet<'gc>(Gc<'gc, RootSetState>);
+
+impl<'gc> Copy for DynamicRootSet<'gc> {}
+
+impl<'gc> Clone for DynamicRootSet<'gc> {
+    fn clone(&self) -> DynamicRootSet<'gc> {
+        *self
+    }
+}
+
+impl<'gc> Debug for DynamicRootSet<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(DynamicRootSet)")
+    }
+}
+
+unsafe impl<'gc> Collect for DynamicRootSet<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc> DynamicRootSet<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> DynamicRootSet<'gc> {
+        DynamicRootSet(Gc::allocate(
+            mc,
+            RootSetState {
+                slots: Rc::new(RefCell::new(Slots {
+                    objects: Vec::new(),
+                    free: Vec::new(),
+                })),
+            },
+        ))
+    }
+
+    /// Adds an object to the set, returning a handle which keeps it alive until every clone of the
+    /// handle has been dropped.
+    ///
+    /// The type of the object is given by a `Rootable` type, which may be produced with the
+    /// `Rootable!` macro.
+    pub fn stash<R: for<'a> Rootable<'a>>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        gc: Gc<'gc, Root<'gc, R>>,
+    ) -> DynamicRoot<R>
+    where
+        Root<'gc, R>: Collect,
+    {
+        let ptr = gc.ptr.cast::<GcBox<()>>();
+        let index = {
+            let mut slots = self.0.slots.borrow_mut();
+            let slot = Slot {
+                ptr: Some(ptr),
+                handles: 1,
+            };
+            if let Some(index) = slots.free.pop() {
+                slots.objects[index] = slot;
+                index
+            } else {
+                slots.objects.push(slot);
+                slots.objects.len() - 1
+            }
+        };
+        Gc::write_barrier(mc, self.0);
+        DynamicRoot {
+            slots: self.0.slots.clone(),
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the object held by the given handle.
+    ///
+    /// Panics if the handle was not returned by `stash` on this set.
+    pub fn fetch<R: for<'a> Rootable<'a>>(&self, root: &DynamicRoot<R>) -> Gc<'gc, Root<'gc, R>>
+    where
+        Root<'gc, R>: Collect,
+    {
+        assert!(
+            self.contains(root),
+            "DynamicRoot fetched from a DynamicRootSet it does not belong to"
+        );
+        let ptr = self.0.slots.borrow().objects[root.index].ptr.unwrap();
+        // SAFETY: The handle was returned by `stash` on this set, so the object belongs to this
+        // arena, has the type named by `R`, and has been kept alive by the set since.
+        unsafe { Gc::from_ptr(ptr.cast()) }
+    }
+
+    /// Returns true if the given handle was returned by `stash` on this set.
+    pub fn contains<R: for<'a> Rootable<'a>>(&self, root: &DynamicRoot<R>) -> bool {
+        Rc::ptr_eq(&self.0.slots, &root.slots)
+    }
+
+    /// The number of objects kept alive by the set.
+    pub fn len(&self) -> usize {
+        let slots = self.0.slots.borrow();
+        slots.objects.len() - slots.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A handle to an object in a `DynamicRootSet`, returned by `DynamicRootSet::stash`.
+///
+/// The handle is `'static`, so it may be held anywhere outside of the arena, and the object is
+/// kept alive for as long as the handle or any of its clones exist.  A handle may outlive the
+/// arena it came from, though it can then no longer be fetched.
+pub struct DynamicRoot<R: for<'a> Rootable<'a>> {
+    slots: Rc<RefCell<Slots>>,
+    index: usize,
+    _marker: PhantomData<R>,
+}
+
+impl<R: for<'a> Rootable<'a>> Clone for DynamicRoot<R> {
+    fn clone(&self) -> DynamicRoot<R> {
+        self.slots.borrow_mut().objects[self.index].handles += 1;
+        DynamicRoot {
+            slots: self.slots.clone(),
+            index: self.index,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<R: for<'a> Rootable<'a>> Drop for DynamicRoot<R> {
+    fn drop(&mut self) {
+        let mut slots = self.slots.borrow_mut();
+        let slot = &mut slots.objects[self.index];
+        slot.handles -= 1;
+        if slot.handles == 0 {
+            slot.ptr = None;
+            slots.free.push(self.index);
+        }
+    }
+}
+
+impl<R: for<'a> Rootable<'a>> Debug for DynamicRoot<R> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(DynamicRoot)")
+    }
+}
+
+// The slots are shared between the set and its handles, so that handles may free their slot when
+// dropped, even after the arena itself is gone.
+struct RootSetState {
+    slots: Rc<RefCell<Slots>>,
+}
+
+unsafe impl Collect for RootSetState {
+    fn trace(&self, cc: CollectionContext) {
+        for slot in &self.slots.borrow().objects {
+            if let Some(ptr) = slot.ptr {
+                // SAFETY: Only the object header is used when tracing, so the erased type does not
+                // matter.
+                unsafe { cc.trace(ptr) };
+            }
+        }
+    }
+}
+
+struct Slots {
+    objects: Vec<Slot>,
+    // Indexes of every slot which no longer has a handle, for reuse.
+    free: Vec<usize>,
+}
+
+struct Slot {
+    ptr: Option<NonNull<GcBox<()>>>,
+    handles: usize,
+}
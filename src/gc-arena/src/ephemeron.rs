@@ -0,0 +1,147 @@
+use alloc::collections::BTreeMap;
+use core::cell::{Ref, RefCell};
+use core::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+
+/// A table mapping `Gc` pointers to values, where each entry only lives as long as its key.
+///
+/// Keys are compared by pointer identity (as with `Gc::ptr_eq`), and are held weakly: the table by
+/// itself never keeps a key alive.  Values are held "ephemerally", a value is only traced once its
+/// key is proven reachable from somewhere other than the table entry, so a value referencing its
+/// own key does not keep the entry alive.  Once the key of an entry becomes unreachable, the whole
+/// entry is removed from the table and its value is dropped, before the key is freed.
+///
+/// This is the primitive needed to implement something like the JavaScript `WeakMap` type.
+pub struct EphemeronTable<'gc, K: 'gc + Collect, V: 'gc + Collect>(
+    Gc<'gc, EphemeronState<'gc, K, V>>,
+);
+
+impl<'gc, K: Collect + 'gc, V: Collect + 'gc> Copy for EphemeronTable<'gc, K, V> {}
+
+impl<'gc, K: Collect + 'gc, V: Collect + 'gc> Clone for EphemeronTable<'gc, K, V> {
+    fn clone(&self) -> EphemeronTable<'gc, K, V> {
+        *self
+    }
+}
+
+impl<'gc, K: 'gc + Collect, V: 'gc + Collect> Debug for EphemeronTable<'gc, K, V> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(EphemeronTable)")
+    }
+}
+
+unsafe impl<'gc, K: 'gc + Collect, V: 'gc + Collect> Collect for EphemeronTable<'gc, K, V> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, K: 'gc + Collect, V: 'gc + Collect> EphemeronTable<'gc, K, V> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> EphemeronTable<'gc, K, V> {
+        let state = Gc::allocate(
+            mc,
+            EphemeronState {
+                entries: RefCell::new(BTreeMap::new()),
+            },
+        );
+        unsafe {
+            mc.add_ephemeron_table(state.ptr);
+        }
+        EphemeronTable(state)
+    }
+
+    /// Insert a value for the given key, returning the previous value if there was one.
+    pub fn insert(&self, _mc: MutationContext<'gc, '_>, key: Gc<'gc, K>, value: V) -> Option<V> {
+        // No write barrier is required here, the collector re-examines every entry in every
+        // reachable table once all other objects have been marked.
+        self.0
+            .entries
+            .borrow_mut()
+            .insert(key_addr(key), (key, value))
+            .map(|(_, v)| v)
+    }
+
+    pub fn get(&self, key: Gc<'gc, K>) -> Option<Ref<'_, V>> {
+        Ref::filter_map(self.0.entries.borrow(), |entries| {
+            entries.get(&key_addr(key)).map(|(_, v)| v)
+        })
+        .ok()
+    }
+
+    pub fn contains_key(&self, key: Gc<'gc, K>) -> bool {
+        self.0.entries.borrow().contains_key(&key_addr(key))
+    }
+
+    pub fn remove(&self, key: Gc<'gc, K>) -> Option<V> {
+        self.0
+            .entries
+            .borrow_mut()
+            .remove(&key_addr(key))
+            .map(|(_, v)| v)
+    }
+
+    /// The number of entries currently in the table.  Entries with unreachable keys are only
+    /// removed at the end of a collection cycle, so may still be counted here.
+    pub fn len(&self) -> usize {
+        self.0.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.borrow().is_empty()
+    }
+
+    pub fn ptr_eq(this: EphemeronTable<'gc, K, V>, other: EphemeronTable<'gc, K, V>) -> bool {
+        Gc::ptr_eq(this.0, other.0)
+    }
+}
+
+// Implemented by the inner state of ephemeron tables, so that the collector can trace the values of
+// entries whose keys are reachable, and remove those which are not.
+pub(crate) trait TraceEphemerons: Collect {
+    // Called repeatedly once the gray queue is empty, must trace the value of every entry whose key
+    // has been marked.
+    unsafe fn trace_ephemerons(&self, cc: CollectionContext);
+
+    // Called once marking is complete, must remove every entry whose key has not been marked.
+    unsafe fn clear_dead(&self, cc: CollectionContext);
+}
+
+struct EphemeronState<'gc, K: 'gc + Collect, V: 'gc + Collect> {
+    // Entries are indexed by the address of their key.  The key itself is kept in the entry so that
+    // its mark state may be checked, but it is never traced.  Entries are always removed before
+    // their key is freed, so addresses can never be reused while an entry is present.
+    entries: RefCell<BTreeMap<usize, (Gc<'gc, K>, V)>>,
+}
+
+// Nothing is traced here, the collector handles entries through `TraceEphemerons`.
+unsafe impl<'gc, K: 'gc + Collect, V: 'gc + Collect> Collect for EphemeronState<'gc, K, V> {
+    #[inline]
+    fn needs_trace() -> bool {
+        false
+    }
+}
+
+impl<'gc, K: 'gc + Collect, V: 'gc + Collect> TraceEphemerons for EphemeronState<'gc, K, V> {
+    unsafe fn trace_ephemerons(&self, cc: CollectionContext) {
+        if V::needs_trace() {
+            for (key, value) in self.entries.borrow().values() {
+                if cc.is_marked(key.ptr) {
+                    value.trace(cc);
+                }
+            }
+        }
+    }
+
+    unsafe fn clear_dead(&self, cc: CollectionContext) {
+        self.entries
+            .borrow_mut()
+            .retain(|_, (key, _)| cc.is_marked(key.ptr));
+    }
+}
+
+fn key_addr<K: Collect>(key: Gc<'_, K>) -> usize {
+    Gc::as_ptr(key) as usize
+}
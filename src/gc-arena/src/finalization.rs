@@ -0,0 +1,127 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+
+/// A queue of objects that will be resurrected instead of freed once they become unreachable.
+///
+/// Objects are registered for finalization with `FinalizationQueue::register`.  Registration does
+/// not keep an object alive, but when the collector determines that a registered object is no
+/// longer reachable, rather than freeing it, the object (along with everything it points to) is
+/// kept alive and moved into this queue.  Objects in the queue can then be taken out with
+/// `FinalizationQueue::pop` inside `mutate`, where they may be freely accessed with a
+/// `MutationContext`, which is not possible from inside `Drop::drop`.
+///
+/// An object is unregistered once it is placed in the queue, so once the object is taken from the
+/// queue and becomes unreachable again, it will be freed normally (unless it has been registered
+/// again).  Since resurrected objects are kept alive, `GcWeak` pointers to them may also still be
+/// upgraded while they are waiting in the queue.
+///
+/// If the queue itself becomes unreachable, any objects registered with it are treated normally
+/// and will be freed without being resurrected.
+pub struct FinalizationQueue<'gc, T: 'gc + Collect>(Gc<'gc, FinalizationState<'gc, T>>);
+
+impl<'gc, T: Collect + 'gc> Copy for FinalizationQueue<'gc, T> {}
+
+impl<'gc, T: Collect + 'gc> Clone for FinalizationQueue<'gc, T> {
+    fn clone(&self) -> FinalizationQueue<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Debug for FinalizationQueue<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(FinalizationQueue)")
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for FinalizationQueue<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: 'gc + Collect> FinalizationQueue<'gc, T> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> FinalizationQueue<'gc, T> {
+        let state = Gc::allocate(
+            mc,
+            FinalizationState {
+                registered: RefCell::new(Vec::new()),
+                ready: RefCell::new(VecDeque::new()),
+            },
+        );
+        unsafe {
+            mc.add_finalization_queue(state.ptr);
+        }
+        FinalizationQueue(state)
+    }
+
+    /// Register the given object to be moved into this queue once it becomes unreachable.
+    ///
+    /// Registering the same object more than once will cause it to be placed into the queue more
+    /// than once.
+    pub fn register(&self, mc: MutationContext<'gc, '_>, gc: Gc<'gc, T>) {
+        unsafe {
+            mc.finalization_barrier(gc.ptr);
+        }
+        self.0.registered.borrow_mut().push(gc);
+    }
+
+    /// Take the next unreachable object out of the queue.
+    pub fn pop(&self) -> Option<Gc<'gc, T>> {
+        self.0.ready.borrow_mut().pop_front()
+    }
+
+    /// The number of unreachable objects currently waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.0.ready.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.ready.borrow().is_empty()
+    }
+
+    pub fn ptr_eq(this: FinalizationQueue<'gc, T>, other: FinalizationQueue<'gc, T>) -> bool {
+        Gc::ptr_eq(this.0, other.0)
+    }
+}
+
+// Implemented by the inner state of finalization queues, so that the collector can find objects
+// which must be resurrected.
+pub(crate) trait Resurrect: Collect {
+    // Called once per collection cycle, after all reachable objects have been marked.  Must move
+    // every registered object which has not been marked into the ready queue, and trace it.
+    unsafe fn resurrect(&self, cc: CollectionContext);
+}
+
+struct FinalizationState<'gc, T: 'gc + Collect> {
+    // Registered objects are intentionally *not* traced, the collector is responsible for keeping
+    // them alive by calling `Resurrect::resurrect` before any of them can be freed.
+    registered: RefCell<Vec<Gc<'gc, T>>>,
+    ready: RefCell<VecDeque<Gc<'gc, T>>>,
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for FinalizationState<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.ready.borrow().trace(cc);
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Resurrect for FinalizationState<'gc, T> {
+    unsafe fn resurrect(&self, cc: CollectionContext) {
+        let mut ready = self.ready.borrow_mut();
+        self.registered.borrow_mut().retain(|&gc| {
+            if cc.is_marked(gc.ptr) {
+                true
+            } else {
+                cc.trace(gc.ptr);
+                ready.push_back(gc);
+                false
+            }
+        });
+    }
+}
@@ -1,11 +1,16 @@
+use alloc::vec::Vec;
 use core::fmt::{self, Debug};
 use core::marker::PhantomData;
 use core::ops::Deref;
 use core::ptr::NonNull;
 
+use crate::arena::HeapLimitExceeded;
+use crate::barrier::Write;
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
 use crate::gc_weak::GcWeak;
+#[cfg(feature = "poison")]
+use crate::types::GcBoxHeader;
 use crate::types::{GcBox, Invariant};
 
 /// A garbage collected pointer to a type T.  Implements Copy, and is implemented as a plain machine
@@ -13,12 +18,16 @@ use crate::types::{GcBox, Invariant};
 /// through "generativity" such `Gc` pointers may not escape the arena they were born in or be
 /// stored inside TLS.  This, combined with correct `Collect` implementations, means that `Gc`
 /// pointers will never be dangling and are always safe to access.
-pub struct Gc<'gc, T: 'gc + Collect> {
+///
+/// `T` may also be unsized: slices and strings can be allocated directly with `Gc::from_iter`,
+/// `Gc::from_slice` and `Gc::from_str`, and a `Gc` may be converted to point to a trait object
+/// (for any trait with `Collect` as a supertrait) with the `unsize!` macro.
+pub struct Gc<'gc, T: 'gc + Collect + ?Sized> {
     pub(crate) ptr: NonNull<GcBox<T>>,
     _invariant: Invariant<'gc>,
 }
 
-impl<'gc, T: 'gc + Collect> Debug for Gc<'gc, T> {
+impl<'gc, T: 'gc + Collect + ?Sized> Debug for Gc<'gc, T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Gc")
             .field("ptr", unsafe { &self.ptr.as_ref().value.get() })
@@ -26,15 +35,15 @@ impl<'gc, T: 'gc + Collect> Debug for Gc<'gc, T> {
     }
 }
 
-impl<'gc, T: Collect + 'gc> Copy for Gc<'gc, T> {}
+impl<'gc, T: Collect + 'gc + ?Sized> Copy for Gc<'gc, T> {}
 
-impl<'gc, T: Collect + 'gc> Clone for Gc<'gc, T> {
+impl<'gc, T: Collect + 'gc + ?Sized> Clone for Gc<'gc, T> {
     fn clone(&self) -> Gc<'gc, T> {
         *self
     }
 }
 
-unsafe impl<'gc, T: 'gc + Collect> Collect for Gc<'gc, T> {
+unsafe impl<'gc, T: 'gc + Collect + ?Sized> Collect for Gc<'gc, T> {
     fn trace(&self, cc: CollectionContext) {
         unsafe {
             cc.trace(self.ptr);
@@ -42,15 +51,23 @@ unsafe impl<'gc, T: 'gc + Collect> Collect for Gc<'gc, T> {
     }
 }
 
-impl<'gc, T: Collect + 'gc> Deref for Gc<'gc, T> {
+impl<'gc, T: Collect + 'gc + ?Sized> Deref for Gc<'gc, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
+        #[cfg(feature = "poison")]
+        unsafe {
+            GcBoxHeader::assert_not_poisoned(self.ptr.cast())
+        };
         unsafe { &*self.ptr.as_ref().value.get() }
     }
 }
 
 impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
+    /// Allocates a new object holding the given value.
+    ///
+    /// Panics if this would exceed the arena's maximum heap size, see
+    /// `ArenaParameters::set_max_heap_size`.
     pub fn allocate(mc: MutationContext<'gc, '_>, t: T) -> Gc<'gc, T> {
         Gc {
             ptr: unsafe { mc.allocate(t) },
@@ -58,6 +75,89 @@ impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
         }
     }
 
+    /// Like `allocate`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.
+    pub fn try_allocate(
+        mc: MutationContext<'gc, '_>,
+        t: T,
+    ) -> Result<Gc<'gc, T>, HeapLimitExceeded> {
+        Ok(Gc {
+            ptr: unsafe { mc.try_allocate(t)? },
+            _invariant: PhantomData,
+        })
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Gc<'gc, [T]> {
+    /// Allocate a slice holding every item produced by the given iterator, without any extra
+    /// indirection.
+    ///
+    /// Panics if this would exceed the arena's maximum heap size, see
+    /// `ArenaParameters::set_max_heap_size`.
+    pub fn from_iter<I: IntoIterator<Item = T>>(mc: MutationContext<'gc, '_>, iter: I) -> Self {
+        Gc {
+            ptr: unsafe { mc.allocate_slice(iter.into_iter().collect::<Vec<T>>()) },
+            _invariant: PhantomData,
+        }
+    }
+
+    /// Like `from_iter`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.  The iterator is always consumed.
+    pub fn try_from_iter<I: IntoIterator<Item = T>>(
+        mc: MutationContext<'gc, '_>,
+        iter: I,
+    ) -> Result<Self, HeapLimitExceeded> {
+        Ok(Gc {
+            ptr: unsafe { mc.try_allocate_slice(iter.into_iter().collect::<Vec<T>>())? },
+            _invariant: PhantomData,
+        })
+    }
+
+    /// Allocate a slice holding a clone of every item in the given slice.
+    ///
+    /// Panics if this would exceed the arena's maximum heap size.
+    pub fn from_slice(mc: MutationContext<'gc, '_>, slice: &[T]) -> Self
+    where
+        T: Clone,
+    {
+        Gc::from_iter(mc, slice.iter().cloned())
+    }
+
+    /// Like `from_slice`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.
+    pub fn try_from_slice(
+        mc: MutationContext<'gc, '_>,
+        slice: &[T],
+    ) -> Result<Self, HeapLimitExceeded>
+    where
+        T: Clone,
+    {
+        Gc::try_from_iter(mc, slice.iter().cloned())
+    }
+}
+
+impl<'gc> Gc<'gc, str> {
+    /// Allocate a copy of the given string, without any extra indirection.
+    ///
+    /// Panics if this would exceed the arena's maximum heap size.
+    pub fn from_str(mc: MutationContext<'gc, '_>, s: &str) -> Self {
+        Gc {
+            ptr: unsafe { mc.allocate_str(s) },
+            _invariant: PhantomData,
+        }
+    }
+
+    /// Like `from_str`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.
+    pub fn try_from_str(mc: MutationContext<'gc, '_>, s: &str) -> Result<Self, HeapLimitExceeded> {
+        Ok(Gc {
+            ptr: unsafe { mc.try_allocate_str(s)? },
+            _invariant: PhantomData,
+        })
+    }
+}
+
+impl<'gc, T: 'gc + Collect + ?Sized> Gc<'gc, T> {
     pub fn downgrade(this: Gc<'gc, T>) -> GcWeak<'gc, T> {
         GcWeak { inner: this }
     }
@@ -71,11 +171,81 @@ impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
         }
     }
 
+    /// Executes the write barrier for this pointer and returns a `&Write` reference to its
+    /// contents, which can be projected to individual fields with `field!` and used to mutate any
+    /// `Lock` or `RefLock` values held inside.
+    ///
+    /// The returned reference only lives as long as the borrow of the `MutationContext`, so that it
+    /// cannot be stored inside the arena and used to mutate the object after a later call to
+    /// `mutate`, without another write barrier.
+    pub fn write<'context>(mc: MutationContext<'gc, 'context>, gc: Self) -> &'context Write<T>
+    where
+        'gc: 'context,
+    {
+        Gc::write_barrier(mc, gc);
+        unsafe { Write::assume(&*gc.ptr.as_ref().value.get()) }
+    }
+
     pub fn ptr_eq(this: Gc<'gc, T>, other: Gc<'gc, T>) -> bool {
-        Gc::as_ptr(this) == Gc::as_ptr(other)
+        // Only the addresses are compared, pointer metadata such as vtables may legitimately differ.
+        Gc::as_ptr(this) as *const u8 == Gc::as_ptr(other) as *const u8
     }
 
     pub fn as_ptr(gc: Gc<'gc, T>) -> *const T {
         unsafe { gc.ptr.as_ref().value.get() }
     }
+
+    // The pointer must point to a live object of type `T` allocated in the arena branded with 'gc.
+    pub(crate) unsafe fn from_ptr(ptr: NonNull<GcBox<T>>) -> Gc<'gc, T> {
+        Gc {
+            ptr,
+            _invariant: PhantomData,
+        }
+    }
+
+    // Used by the `unsize!` macro, `coerce` must return the pointer it was given.
+    #[doc(hidden)]
+    pub unsafe fn __unsize_with<U: 'gc + Collect + ?Sized>(
+        self,
+        coerce: impl FnOnce(NonNull<GcBox<T>>) -> NonNull<GcBox<U>>,
+    ) -> Gc<'gc, U> {
+        Gc {
+            ptr: coerce(self.ptr),
+            _invariant: PhantomData,
+        }
+    }
+}
+
+/// Converts a `Gc` or `GcWeak` pointer to a pointer to an unsized type, for example a trait object
+/// or a slice, in the same way that `&T` can be coerced to `&dyn Trait` or `&[T; N]` to `&[T]`.
+///
+/// ```
+/// # use gc_arena::{rootless_arena, unsize, Collect, Gc};
+/// trait Shape: Collect {
+///     fn area(&self) -> f64;
+/// }
+///
+/// #[derive(Collect)]
+/// #[collect(require_static)]
+/// struct Square(f64);
+///
+/// impl Shape for Square {
+///     fn area(&self) -> f64 {
+///         self.0 * self.0
+///     }
+/// }
+///
+/// rootless_arena(|mc| {
+///     let shape: Gc<dyn Shape> = unsize!(Gc::allocate(mc, Square(2.0)) => dyn Shape);
+///     assert_eq!(shape.area(), 4.0);
+/// });
+/// ```
+#[macro_export]
+macro_rules! unsize {
+    ($gc:expr => $ty:ty) => {{
+        let gc = $gc;
+        // The closure simply returns its argument, so if this compiles, the only possible
+        // conversion is a valid unsizing coercion.
+        unsafe { gc.__unsize_with(|ptr| -> ::core::ptr::NonNull<$crate::__GcBox<$ty>> { ptr }) }
+    }};
 }
@@ -1,6 +1,7 @@
 use core::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut};
 use core::fmt::{self, Debug};
 
+use crate::arena::HeapLimitExceeded;
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
 use crate::gc::Gc;
@@ -43,6 +44,20 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
         ))
     }
 
+    /// Like `allocate`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.
+    pub fn try_allocate(
+        mc: MutationContext<'gc, '_>,
+        t: T,
+    ) -> Result<GcCell<'gc, T>, HeapLimitExceeded> {
+        Ok(GcCell(Gc::try_allocate(
+            mc,
+            GcRefCell {
+                cell: RefCell::new(t),
+            },
+        )?))
+    }
+
     pub fn downgrade(this: GcCell<'gc, T>) -> GcWeakCell<'gc, T> {
         GcWeakCell { inner: this }
     }
@@ -91,6 +106,6 @@ pub(crate) struct GcRefCell<T: Collect> {
 
 unsafe impl<'gc, T: Collect + 'gc> Collect for GcRefCell<T> {
     fn trace(&self, cc: CollectionContext) {
-        self.cell.borrow().trace(cc);
+        cc.trace_ref_cell(&self.cell);
     }
 }
@@ -0,0 +1,254 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::RefCell;
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::collections::hash_map::{HashMap, RandomState};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::gc_weak::{GcWeak, PruneDead};
+
+/// Interns immutable garbage collected values, so that equal values share a single allocation.
+///
+/// Interning a value returns the existing `Gc` for an equal value if there is one, otherwise the
+/// value is allocated and remembered.  Interned values are only held weakly, so a value which is no
+/// longer referenced anywhere else is collected as usual, and its entry is automatically removed at
+/// the end of the collection cycle.
+///
+/// Since equal values share an allocation, interned values may be compared with `Gc::ptr_eq`.
+/// They must not be mutated in any way that changes their hash or equality.
+pub struct GcInterner<'gc, T, S = RandomState>(Gc<'gc, InternerState<'gc, T, S>>)
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher;
+
+impl<'gc, T, S> Copy for GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+}
+
+impl<'gc, T, S> Clone for GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    fn clone(&self) -> GcInterner<'gc, T, S> {
+        *self
+    }
+}
+
+impl<'gc, T, S> Debug for GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(GcInterner)")
+    }
+}
+
+unsafe impl<'gc, T, S> Collect for GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T> GcInterner<'gc, T, RandomState>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+{
+    pub fn new(mc: MutationContext<'gc, '_>) -> GcInterner<'gc, T, RandomState> {
+        GcInterner::with_hasher(mc, RandomState::new())
+    }
+}
+
+impl<'gc, T, S> GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    pub fn with_hasher(mc: MutationContext<'gc, '_>, hash_builder: S) -> GcInterner<'gc, T, S> {
+        let state = Gc::allocate(
+            mc,
+            InternerState {
+                hash_builder,
+                buckets: RefCell::new(HashMap::default()),
+            },
+        );
+        unsafe {
+            mc.add_weak_collection(state.ptr);
+        }
+        GcInterner(state)
+    }
+
+    /// Returns the interned value equal to `value`, if there is one and it has not been collected.
+    pub fn get<Q>(&self, mc: MutationContext<'gc, '_>, value: &Q) -> Option<Gc<'gc, T>>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.find(mc, self.0.hash(value), value)
+    }
+
+    /// Returns the interned value equal to `value`, or calls `allocate` to create a new one and
+    /// interns that.  The value returned from `allocate` must be equal to `value`.
+    pub fn intern_with<Q>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        value: &Q,
+        allocate: impl FnOnce(&Q) -> Gc<'gc, T>,
+    ) -> Gc<'gc, T>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.0.hash(value);
+        if let Some(gc) = self.find(mc, hash, value) {
+            return gc;
+        }
+
+        let gc = allocate(value);
+        debug_assert!((*gc).borrow() == value);
+        self.insert(mc, hash, gc);
+        gc
+    }
+
+    /// The number of interned values.  This may include values which have been collected during
+    /// the current collection cycle, which will be removed once the cycle completes.
+    pub fn len(&self) -> usize {
+        self.0
+            .buckets
+            .borrow()
+            .values()
+            .map(|bucket| bucket.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.buckets.borrow().is_empty()
+    }
+
+    pub fn ptr_eq(this: GcInterner<'gc, T, S>, other: GcInterner<'gc, T, S>) -> bool {
+        Gc::ptr_eq(this.0, other.0)
+    }
+
+    fn find<Q>(&self, mc: MutationContext<'gc, '_>, hash: u64, value: &Q) -> Option<Gc<'gc, T>>
+    where
+        T: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        // Dead values may not be compared, so every entry is upgraded first.
+        self.0
+            .buckets
+            .borrow()
+            .get(&hash)?
+            .iter()
+            .filter_map(|weak| weak.upgrade(mc))
+            .find(|gc| (**gc).borrow() == value)
+    }
+
+    fn insert(&self, mc: MutationContext<'gc, '_>, hash: u64, gc: Gc<'gc, T>) {
+        self.0
+            .buckets
+            .borrow_mut()
+            .entry(hash)
+            .or_default()
+            .push(Gc::downgrade(gc));
+        Gc::write_barrier(mc, self.0);
+    }
+}
+
+impl<'gc, T, S> GcInterner<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect,
+    S: 'static + BuildHasher,
+{
+    /// Returns the interned value equal to `value`, allocating and interning `value` if there is
+    /// none.
+    pub fn intern(&self, mc: MutationContext<'gc, '_>, value: T) -> Gc<'gc, T> {
+        let hash = self.0.hash(&value);
+        if let Some(gc) = self.find(mc, hash, &value) {
+            return gc;
+        }
+        let gc = Gc::allocate(mc, value);
+        self.insert(mc, hash, gc);
+        gc
+    }
+}
+
+impl<'gc, S> GcInterner<'gc, str, S>
+where
+    S: 'static + BuildHasher,
+{
+    /// Returns the interned string equal to `value`, allocating and interning a copy of it if there
+    /// is none.
+    pub fn intern_str(&self, mc: MutationContext<'gc, '_>, value: &str) -> Gc<'gc, str> {
+        self.intern_with(mc, value, |s| Gc::from_str(mc, s))
+    }
+}
+
+struct InternerState<'gc, T: 'gc + Collect + ?Sized, S> {
+    hash_builder: S,
+    // Interned values are stored in buckets by their hash, since the values themselves are only
+    // held weakly and cannot be used as keys.
+    buckets: RefCell<HashMap<u64, Vec<GcWeak<'gc, T>>, BuildHasherDefault<HashHasher>>>,
+}
+
+impl<'gc, T: 'gc + Collect + ?Sized, S: BuildHasher> InternerState<'gc, T, S> {
+    fn hash<Q: ?Sized + Hash>(&self, value: &Q) -> u64 {
+        self.hash_builder.hash_one(value)
+    }
+}
+
+unsafe impl<'gc, T, S> Collect for InternerState<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    fn trace(&self, cc: CollectionContext) {
+        for bucket in self.buckets.borrow().values() {
+            for weak in bucket {
+                weak.trace(cc);
+            }
+        }
+    }
+}
+
+impl<'gc, T, S> PruneDead for InternerState<'gc, T, S>
+where
+    T: 'gc + Eq + Hash + Collect + ?Sized,
+    S: 'static + BuildHasher,
+{
+    unsafe fn prune_dead(&self) {
+        self.buckets.borrow_mut().retain(|_, bucket| {
+            bucket.retain(|weak| weak.inner.ptr.as_ref().header.flags.alive());
+            !bucket.is_empty()
+        });
+    }
+}
+
+// The bucket keys are already hashes, so they are used as-is.
+#[derive(Default)]
+struct HashHasher(u64);
+
+impl Hasher for HashHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _: &[u8]) {
+        unreachable!()
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
@@ -0,0 +1,97 @@
+use core::cell::Cell;
+use core::fmt::{self, Debug};
+
+use crate::arena::HeapLimitExceeded;
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+
+/// A garbage collected pointer to a `Copy` type T that may be safely mutated, the `Cell` analogue of
+/// `GcCell`.
+///
+/// Rather than handing out borrows of its contents, the value is copied in and out as a whole, so
+/// there is no borrow flag to check and no way to panic.  Setting the value is always accompanied
+/// by a call to `Gc::write_barrier`, so `T` may itself hold `Gc` pointers.
+pub struct GcLock<'gc, T: 'gc + Collect + Copy>(Gc<'gc, GcLockCell<T>>);
+
+impl<'gc, T: Collect + Copy + 'gc> Copy for GcLock<'gc, T> {}
+
+impl<'gc, T: Collect + Copy + 'gc> Clone for GcLock<'gc, T> {
+    fn clone(&self) -> GcLock<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: 'gc + Collect + Copy + Debug> Debug for GcLock<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("GcLock").field(&self.get()).finish()
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Collect + Copy> Collect for GcLock<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: 'gc + Collect + Copy> GcLock<'gc, T> {
+    pub fn allocate(mc: MutationContext<'gc, '_>, t: T) -> GcLock<'gc, T> {
+        GcLock(Gc::allocate(mc, GcLockCell { cell: Cell::new(t) }))
+    }
+
+    /// Like `allocate`, but returns an error rather than panicking if this would exceed the
+    /// arena's maximum heap size.
+    pub fn try_allocate(
+        mc: MutationContext<'gc, '_>,
+        t: T,
+    ) -> Result<GcLock<'gc, T>, HeapLimitExceeded> {
+        Ok(GcLock(Gc::try_allocate(
+            mc,
+            GcLockCell { cell: Cell::new(t) },
+        )?))
+    }
+
+    pub fn ptr_eq(this: GcLock<'gc, T>, other: GcLock<'gc, T>) -> bool {
+        this.as_ptr() == other.as_ptr()
+    }
+
+    pub fn as_ptr(self) -> *mut T {
+        self.0.cell.as_ptr()
+    }
+
+    pub fn get(&self) -> T {
+        self.0.cell.get()
+    }
+
+    pub fn set(&self, mc: MutationContext<'gc, '_>, t: T) {
+        Gc::write_barrier(mc, self.0);
+        self.0.cell.set(t);
+    }
+
+    pub fn replace(&self, mc: MutationContext<'gc, '_>, t: T) -> T {
+        Gc::write_barrier(mc, self.0);
+        self.0.cell.replace(t)
+    }
+
+    pub fn take(&self, mc: MutationContext<'gc, '_>) -> T
+    where
+        T: Default,
+    {
+        self.replace(mc, T::default())
+    }
+}
+
+struct GcLockCell<T: Collect + Copy> {
+    cell: Cell<T>,
+}
+
+unsafe impl<T: Collect + Copy> Collect for GcLockCell<T> {
+    #[inline]
+    fn needs_trace() -> bool {
+        T::needs_trace()
+    }
+
+    fn trace(&self, cc: CollectionContext) {
+        self.cell.get().trace(cc);
+    }
+}
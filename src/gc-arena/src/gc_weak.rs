@@ -1,42 +1,59 @@
 use crate::collect::Collect;
 use crate::gc::Gc;
-use crate::types::GcColor;
+use crate::types::GcBox;
 use crate::{CollectionContext, MutationContext};
 
 use core::fmt::{self, Debug};
+use core::ptr::NonNull;
 
-pub struct GcWeak<'gc, T: 'gc + Collect> {
+pub struct GcWeak<'gc, T: 'gc + Collect + ?Sized> {
     pub(crate) inner: Gc<'gc, T>,
 }
 
-impl<'gc, T: Collect + 'gc> Copy for GcWeak<'gc, T> {}
+impl<'gc, T: Collect + 'gc + ?Sized> Copy for GcWeak<'gc, T> {}
 
-impl<'gc, T: Collect + 'gc> Clone for GcWeak<'gc, T> {
+impl<'gc, T: Collect + 'gc + ?Sized> Clone for GcWeak<'gc, T> {
     fn clone(&self) -> GcWeak<'gc, T> {
         *self
     }
 }
 
-impl<'gc, T: 'gc + Collect> Debug for GcWeak<'gc, T> {
+impl<'gc, T: 'gc + Collect + ?Sized> Debug for GcWeak<'gc, T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "(GcWeak)")
     }
 }
 
-unsafe impl<'gc, T: 'gc + Collect> Collect for GcWeak<'gc, T> {
-    fn trace(&self, _cc: CollectionContext) {
+unsafe impl<'gc, T: 'gc + Collect + ?Sized> Collect for GcWeak<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
         unsafe {
-            let gc = self.inner.ptr.as_ref();
-            gc.flags.set_has_weak_ref(true);
-            if gc.flags.color() == GcColor::FreshWhite {
-                gc.flags.set_color(GcColor::White);
-            }
+            cc.trace_weak(self.inner.ptr);
         }
     }
 }
 
-impl<'gc, T: Collect + 'gc> GcWeak<'gc, T> {
+impl<'gc, T: Collect + 'gc + ?Sized> GcWeak<'gc, T> {
     pub fn upgrade(&self, mc: MutationContext<'gc, '_>) -> Option<Gc<'gc, T>> {
         unsafe { mc.upgrade(self.inner.ptr).then(|| self.inner) }
     }
+
+    // Used by the `unsize!` macro, `coerce` must return the pointer it was given.
+    #[doc(hidden)]
+    pub unsafe fn __unsize_with<U: 'gc + Collect + ?Sized>(
+        self,
+        coerce: impl FnOnce(NonNull<GcBox<T>>) -> NonNull<GcBox<U>>,
+    ) -> GcWeak<'gc, U> {
+        GcWeak {
+            inner: self.inner.__unsize_with(coerce),
+        }
+    }
+}
+
+// Implemented by the inner state of weak collections, so that the collector can remove entries
+// pointing to dead objects once they can no longer be upgraded.
+pub(crate) trait PruneDead: Collect {
+    // Called once a sweep has completed, must remove every entry whose target has been dropped.
+    // Since dropped objects are only freed during the *next* sweep if nothing has traced a weak
+    // pointer to them, removing entries here also allows the dead objects to be freed.
+    unsafe fn prune_dead(&self);
 }
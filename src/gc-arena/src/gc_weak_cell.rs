@@ -1,6 +1,5 @@
-use crate::types::GcColor;
 use crate::GcCell;
-use crate::{collect::Collect, MutationContext};
+use crate::{collect::Collect, CollectionContext, MutationContext};
 
 use core::fmt::{self, Debug};
 
@@ -23,14 +22,9 @@ impl<'gc, T: 'gc + Collect> Debug for GcWeakCell<'gc, T> {
 }
 
 unsafe impl<'gc, T: 'gc + Collect> Collect for GcWeakCell<'gc, T> {
-    fn trace(&self, _cc: crate::CollectionContext) {
+    fn trace(&self, cc: CollectionContext) {
         unsafe {
-            let gc = self.inner.get_inner().ptr.as_ref();
-
-            gc.flags.set_has_weak_ref(true);
-            if gc.flags.color() == GcColor::FreshWhite {
-                gc.flags.set_color(GcColor::White);
-            }
+            cc.trace_weak(self.inner.get_inner().ptr);
         }
     }
 }
@@ -0,0 +1,223 @@
+use core::borrow::Borrow;
+use core::cell::{Ref, RefCell};
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::{self, HashMap, RandomState};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::gc_weak::{GcWeak, PruneDead};
+
+/// A hash map from keys to weak pointers.
+///
+/// Keys are held normally, but values are held weakly and do not keep their targets alive.  Once
+/// the target of an entry has been collected, lookups for the entry will fail and iteration will
+/// skip it, and the entry is automatically removed from the map at the end of the collection
+/// cycle.  This makes `GcWeakMap` well suited for caches of `Gc` pointers which should not keep
+/// the cached objects alive.
+pub struct GcWeakMap<'gc, K, V, S = RandomState>(Gc<'gc, WeakMapState<'gc, K, V, S>>)
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher;
+
+impl<'gc, K, V, S> Copy for GcWeakMap<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+}
+
+impl<'gc, K, V, S> Clone for GcWeakMap<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    fn clone(&self) -> GcWeakMap<'gc, K, V, S> {
+        *self
+    }
+}
+
+impl<'gc, K, V, S> Debug for GcWeakMap<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(GcWeakMap)")
+    }
+}
+
+unsafe impl<'gc, K, V, S> Collect for GcWeakMap<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, K, V> GcWeakMap<'gc, K, V, RandomState>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+{
+    pub fn new(mc: MutationContext<'gc, '_>) -> GcWeakMap<'gc, K, V, RandomState> {
+        GcWeakMap::with_hasher(mc, RandomState::new())
+    }
+}
+
+impl<'gc, K, V, S> GcWeakMap<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    pub fn with_hasher(mc: MutationContext<'gc, '_>, hash_builder: S) -> GcWeakMap<'gc, K, V, S> {
+        let state = Gc::allocate(
+            mc,
+            WeakMapState {
+                entries: RefCell::new(HashMap::with_hasher(hash_builder)),
+            },
+        );
+        unsafe {
+            mc.add_weak_collection(state.ptr);
+        }
+        GcWeakMap(state)
+    }
+
+    /// Inserts a weak pointer to `value` for the given key, returning the previous value if there
+    /// was one and it has not been collected.
+    pub fn insert(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        key: K,
+        value: Gc<'gc, V>,
+    ) -> Option<Gc<'gc, V>> {
+        let prev = self
+            .0
+            .entries
+            .borrow_mut()
+            .insert(key, Gc::downgrade(value));
+        Gc::write_barrier(mc, self.0);
+        prev.and_then(|weak| weak.upgrade(mc))
+    }
+
+    /// Returns the value for the given key, if it has not been collected.
+    pub fn get<Q>(&self, mc: MutationContext<'gc, '_>, key: &Q) -> Option<Gc<'gc, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.0
+            .entries
+            .borrow()
+            .get(key)
+            .and_then(|weak| weak.upgrade(mc))
+    }
+
+    pub fn contains_key<Q>(&self, mc: MutationContext<'gc, '_>, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get(mc, key).is_some()
+    }
+
+    /// Removes the entry for the given key, returning its value if it has not been collected.
+    pub fn remove<Q>(&self, mc: MutationContext<'gc, '_>, key: &Q) -> Option<Gc<'gc, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.0
+            .entries
+            .borrow_mut()
+            .remove(key)
+            .and_then(|weak| weak.upgrade(mc))
+    }
+
+    /// The number of entries in the map.  This may include entries whose values have been
+    /// collected during the current collection cycle, which will be removed once the cycle
+    /// completes.
+    pub fn len(&self) -> usize {
+        self.0.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.borrow().is_empty()
+    }
+
+    /// Iterates over every entry in the map whose value has not been collected.  The map may not be
+    /// modified while the returned iterator is live.
+    pub fn iter<'a>(&'a self, mc: MutationContext<'gc, 'a>) -> GcWeakMapIter<'a, 'gc, K, V> {
+        let entries = self.0.entries.borrow();
+        // SAFETY: The entries are stored inside a `Gc` which outlives 'a, and cannot be mutated
+        // while we hold the `Ref` guard.
+        let iter = unsafe { (*(&*entries as *const HashMap<K, GcWeak<'gc, V>, S>)).iter() };
+        GcWeakMapIter {
+            iter,
+            _entries: Ref::map(entries, |_| &()),
+            mc,
+        }
+    }
+
+    pub fn ptr_eq(this: GcWeakMap<'gc, K, V, S>, other: GcWeakMap<'gc, K, V, S>) -> bool {
+        Gc::ptr_eq(this.0, other.0)
+    }
+}
+
+pub struct GcWeakMapIter<'a, 'gc, K: 'gc, V: 'gc + Collect> {
+    iter: hash_map::Iter<'a, K, GcWeak<'gc, V>>,
+    // Only held to keep the entries borrowed, erased so that the hasher type does not need to be
+    // named.
+    _entries: Ref<'a, ()>,
+    mc: MutationContext<'gc, 'a>,
+}
+
+impl<'a, 'gc: 'a, K: 'gc, V: 'gc + Collect> Iterator for GcWeakMapIter<'a, 'gc, K, V> {
+    type Item = (&'a K, Gc<'gc, V>);
+
+    fn next(&mut self) -> Option<(&'a K, Gc<'gc, V>)> {
+        let mc = self.mc;
+        self.iter
+            .find_map(|(key, weak)| weak.upgrade(mc).map(|gc| (key, gc)))
+    }
+}
+
+struct WeakMapState<'gc, K, V: 'gc + Collect, S> {
+    entries: RefCell<HashMap<K, GcWeak<'gc, V>, S>>,
+}
+
+unsafe impl<'gc, K, V, S> Collect for WeakMapState<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    fn trace(&self, cc: CollectionContext) {
+        for (key, weak) in self.entries.borrow().iter() {
+            key.trace(cc);
+            weak.trace(cc);
+        }
+    }
+}
+
+impl<'gc, K, V, S> PruneDead for WeakMapState<'gc, K, V, S>
+where
+    K: 'gc + Eq + Hash + Collect,
+    V: 'gc + Collect,
+    S: 'static + BuildHasher,
+{
+    unsafe fn prune_dead(&self) {
+        self.entries
+            .borrow_mut()
+            .retain(|_, weak| weak.inner.ptr.as_ref().header.flags.alive());
+    }
+}
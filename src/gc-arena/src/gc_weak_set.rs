@@ -0,0 +1,142 @@
+use alloc::collections::{btree_map, BTreeMap};
+use core::cell::{Ref, RefCell};
+use core::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::gc_weak::{GcWeak, PruneDead};
+
+/// A set of weak pointers, compared by pointer identity.
+///
+/// Holding an object in the set does not keep it alive.  Once an object in the set has been
+/// collected, it is skipped by iteration, and its entry is automatically removed from the set at
+/// the end of the collection cycle.
+pub struct GcWeakSet<'gc, T: 'gc + Collect>(Gc<'gc, WeakSetState<'gc, T>>);
+
+impl<'gc, T: Collect + 'gc> Copy for GcWeakSet<'gc, T> {}
+
+impl<'gc, T: Collect + 'gc> Clone for GcWeakSet<'gc, T> {
+    fn clone(&self) -> GcWeakSet<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Debug for GcWeakSet<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(GcWeakSet)")
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for GcWeakSet<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: 'gc + Collect> GcWeakSet<'gc, T> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> GcWeakSet<'gc, T> {
+        let state = Gc::allocate(
+            mc,
+            WeakSetState {
+                entries: RefCell::new(BTreeMap::new()),
+            },
+        );
+        unsafe {
+            mc.add_weak_collection(state.ptr);
+        }
+        GcWeakSet(state)
+    }
+
+    /// Adds an object to the set, returning true if it was not already present.
+    pub fn insert(&self, mc: MutationContext<'gc, '_>, gc: Gc<'gc, T>) -> bool {
+        let inserted = self
+            .0
+            .entries
+            .borrow_mut()
+            .insert(addr(gc), Gc::downgrade(gc))
+            .is_none();
+        Gc::write_barrier(mc, self.0);
+        inserted
+    }
+
+    pub fn contains(&self, gc: Gc<'gc, T>) -> bool {
+        self.0.entries.borrow().contains_key(&addr(gc))
+    }
+
+    /// Removes an object from the set, returning true if it was present.
+    pub fn remove(&self, gc: Gc<'gc, T>) -> bool {
+        self.0.entries.borrow_mut().remove(&addr(gc)).is_some()
+    }
+
+    /// The number of entries in the set.  This may include objects which have been collected
+    /// during the current collection cycle, which will be removed once the cycle completes.
+    pub fn len(&self) -> usize {
+        self.0.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.borrow().is_empty()
+    }
+
+    /// Iterates over every object in the set which has not been collected.  The set may not be
+    /// modified while the returned iterator is live.
+    pub fn iter<'a>(&'a self, mc: MutationContext<'gc, 'a>) -> GcWeakSetIter<'a, 'gc, T> {
+        let entries = self.0.entries.borrow();
+        // SAFETY: The entries are stored inside a `Gc` which outlives 'a, and cannot be mutated
+        // while we hold the `Ref` guard.
+        let iter = unsafe { (*(&*entries as *const BTreeMap<usize, GcWeak<'gc, T>>)).values() };
+        GcWeakSetIter {
+            iter,
+            _entries: Ref::map(entries, |_| &()),
+            mc,
+        }
+    }
+
+    pub fn ptr_eq(this: GcWeakSet<'gc, T>, other: GcWeakSet<'gc, T>) -> bool {
+        Gc::ptr_eq(this.0, other.0)
+    }
+}
+
+pub struct GcWeakSetIter<'a, 'gc, T: 'gc + Collect> {
+    iter: btree_map::Values<'a, usize, GcWeak<'gc, T>>,
+    // Only held to keep the entries borrowed.
+    _entries: Ref<'a, ()>,
+    mc: MutationContext<'gc, 'a>,
+}
+
+impl<'a, 'gc, T: 'gc + Collect> Iterator for GcWeakSetIter<'a, 'gc, T> {
+    type Item = Gc<'gc, T>;
+
+    fn next(&mut self) -> Option<Gc<'gc, T>> {
+        let mc = self.mc;
+        self.iter.find_map(|weak| weak.upgrade(mc))
+    }
+}
+
+struct WeakSetState<'gc, T: 'gc + Collect> {
+    // Entries are indexed by the address of their target.  Dead targets are not freed until the
+    // entry pointing to them has been pruned, so addresses can never be reused while an entry is
+    // present.
+    entries: RefCell<BTreeMap<usize, GcWeak<'gc, T>>>,
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for WeakSetState<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        for weak in self.entries.borrow().values() {
+            weak.trace(cc);
+        }
+    }
+}
+
+impl<'gc, T: 'gc + Collect> PruneDead for WeakSetState<'gc, T> {
+    unsafe fn prune_dead(&self) {
+        self.entries
+            .borrow_mut()
+            .retain(|_, weak| weak.inner.ptr.as_ref().header.flags.alive());
+    }
+}
+
+fn addr<T: Collect>(gc: Gc<'_, T>) -> usize {
+    Gc::as_ptr(gc) as usize
+}
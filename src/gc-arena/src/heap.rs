@@ -0,0 +1,517 @@
+use alloc::boxed::Box;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::mem;
+use core::ptr::NonNull;
+
+use crate::allocator::GcAllocator;
+use crate::types::GcBoxHeader;
+
+// Size of every page, pages are also aligned to this so that the page holding any slot can be found
+// by masking the slot's address.
+const PAGE_SIZE: usize = 16 * 1024;
+
+// Every slot in a page is aligned to this, objects with a larger alignment are placed into the
+// large object space.
+const SLOT_ALIGN: usize = 16;
+
+// Objects are rounded up to the nearest size class, objects larger than the largest size class are
+// placed into the large object space.
+const SIZE_CLASSES: [usize; 20] = [
+    16, 32, 48, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 448, 512, 640, 768, 896, 1024,
+];
+
+// The number of words in each per-page bitmap, enough for a page full of the smallest slots.
+const BITMAP_WORDS: usize = PAGE_SIZE / SIZE_CLASSES[0] / 64;
+
+// The header of an object is either at the very start of its slot or, for slices which store their
+// length first, at a power of two offset no greater than `SLOT_ALIGN`.  Pages only ever hold
+// objects with the same header offset, so that every object in a page can be found without knowing
+// its type, which gives this many kinds of page.
+const PAGE_KINDS: usize = SLOT_ALIGN.trailing_zeros() as usize + 1;
+
+// Slots start after the page header, rounded up so that the first slot is properly aligned.
+const HEADER_SIZE: usize = (mem::size_of::<PageHeader>() + SLOT_ALIGN - 1) & !(SLOT_ALIGN - 1);
+
+// The storage for every object in an arena.
+//
+// Small objects are allocated from pages which are each dedicated to a single size class, so that
+// objects of similar sizes are packed together rather than being scattered across the global heap.
+// Larger objects, or objects with unusual alignment, are placed into the "large object space",
+// which allocates each of them individually behind a small header of its own.  Both pages and
+// large objects are obtained from the arena's `GcAllocator`.
+//
+// The heap also holds the mark bit of every object, in a bitmap for every page and in the header of
+// every large object, and knows where every object is, so the collector sweeps by going through the
+// heap a page at a time with `begin_sweep` and `sweep_step` rather than by following pointers from
+// object to object.
+//
+// Pages which have become completely empty are kept around to be reused until
+// `Heap::release_empty_pages` is called, which returns them to the allocator.
+pub(crate) struct Heap {
+    allocator: Box<dyn GcAllocator>,
+    // For every size class, a list of every page of that class.
+    pages: [Cell<Option<NonNull<PageHeader>>>; SIZE_CLASSES.len()],
+    // For every size class and kind of page, a list of the pages which have at least one free
+    // slot.  Full pages are not tracked here until one of their slots is freed.
+    available: [[Cell<Option<NonNull<PageHeader>>>; PAGE_KINDS]; SIZE_CLASSES.len()],
+    // A list of every object in the large object space.
+    large: Cell<Option<NonNull<LargeHeader>>>,
+    // The progress of the current sweep, which goes through the pages of every size class in
+    // order, and then through the large object space.
+    sweep_class: Cell<usize>,
+    sweep_page: Cell<Option<NonNull<PageHeader>>>,
+    sweep_large: Cell<Option<NonNull<LargeHeader>>>,
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        // Every object has been freed by now, so every page must be empty.
+        unsafe {
+            self.release_empty_pages();
+        }
+        debug_assert!(self.pages.iter().all(|head| head.get().is_none()));
+        debug_assert!(self.large.get().is_none());
+    }
+}
+
+impl Heap {
+    pub(crate) fn new(allocator: Box<dyn GcAllocator>) -> Heap {
+        Heap {
+            allocator,
+            pages: Default::default(),
+            available: Default::default(),
+            large: Cell::new(None),
+            sweep_class: Cell::new(SIZE_CLASSES.len()),
+            sweep_page: Cell::new(None),
+            sweep_large: Cell::new(None),
+        }
+    }
+
+    // Allocates memory for an object with the given layout, whose header will be placed at
+    // `header_offset` bytes into the allocation.
+    pub(crate) fn allocate(&self, layout: Layout, header_offset: usize) -> Option<NonNull<u8>> {
+        match size_class(layout) {
+            Some(class) => unsafe { self.allocate_slot(class, header_offset) },
+            None => unsafe { self.allocate_large(layout, header_offset) },
+        }
+    }
+
+    // Safety: `ptr` must have been returned from `Heap::allocate` with the same `layout`, and must
+    // not have been deallocated already.
+    pub(crate) unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        match locate(ptr, layout) {
+            Location::Slot(page, slot) => {
+                let header = page.as_ref();
+                let (word, bit) = bitmap_index(slot);
+                header.allocated[word].set(header.allocated[word].get() & !bit);
+                header.marked[word].set(header.marked[word].get() & !bit);
+
+                let free = ptr.cast::<FreeSlot>();
+                free.as_ptr().write(FreeSlot {
+                    next: header.free.get(),
+                });
+                header.free.set(Some(free));
+                header.used.set(header.used.get() - 1);
+
+                if !header.available.get() {
+                    self.push_available(page);
+                }
+            }
+            Location::Large(large) => {
+                let header = large.as_ref();
+                if self.sweep_large.get() == Some(large) {
+                    self.sweep_large.set(header.next.get());
+                }
+                if let Some(next) = header.next.get() {
+                    next.as_ref().prev.set(header.prev.get());
+                }
+                match header.prev.get() {
+                    Some(prev) => prev.as_ref().next.set(header.next.get()),
+                    None => self.large.set(header.next.get()),
+                }
+                self.allocator
+                    .deallocate(large.cast(), large_layout(layout).unwrap().0);
+            }
+        }
+    }
+
+    // Sets the mark bit of the object in the given allocation, which must have been returned from
+    // `Heap::allocate` with the same `layout`.
+    pub(crate) unsafe fn set_marked(&self, ptr: NonNull<u8>, layout: Layout, marked: bool) {
+        match locate(ptr, layout) {
+            Location::Slot(page, slot) => {
+                let header = page.as_ref();
+                let (word, bit) = bitmap_index(slot);
+                let bits = header.marked[word].get();
+                header.marked[word].set(if marked { bits | bit } else { bits & !bit });
+            }
+            Location::Large(large) => large.as_ref().marked.set(marked),
+        }
+    }
+
+    // Returns true if the object in the given allocation is unmarked and has not yet been swept,
+    // and so will be treated as unreachable by the sweep in progress.  Always false when no sweep
+    // is in progress.
+    pub(crate) unsafe fn is_doomed(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        match locate(ptr, layout) {
+            Location::Slot(page, slot) => {
+                let header = page.as_ref();
+                let (word, bit) = bitmap_index(slot);
+                header.unswept.get() && header.marked[word].get() & bit == 0
+            }
+            Location::Large(large) => {
+                let header = large.as_ref();
+                header.unswept.get() && !header.marked.get()
+            }
+        }
+    }
+
+    // Calls the given function with the header of every object in the heap.
+    pub(crate) unsafe fn for_each_object(&self, mut f: impl FnMut(NonNull<GcBoxHeader>)) {
+        for head in &self.pages {
+            let mut next = head.get();
+            while let Some(page) = next {
+                next = page.as_ref().next_page.get();
+                for word in 0..BITMAP_WORDS {
+                    let mut allocated = page.as_ref().allocated[word].get();
+                    while allocated != 0 {
+                        let bit = allocated.trailing_zeros() as usize;
+                        allocated &= allocated - 1;
+                        f(object_header(page, word * 64 + bit));
+                    }
+                }
+            }
+        }
+
+        let mut next = self.large.get();
+        while let Some(large) = next {
+            next = large.as_ref().next.get();
+            f(large_object_header(large));
+        }
+    }
+
+    // Begins sweeping every object currently in the heap.  Objects allocated while the sweep is in
+    // progress are never swept by it, and neither are they doomed.
+    pub(crate) unsafe fn begin_sweep(&self) {
+        for head in &self.pages {
+            let mut next = head.get();
+            while let Some(page) = next {
+                page.as_ref().unswept.set(true);
+                next = page.as_ref().next_page.get();
+            }
+        }
+
+        let mut next = self.large.get();
+        while let Some(large) = next {
+            large.as_ref().unswept.set(true);
+            next = large.as_ref().next.get();
+        }
+
+        self.sweep_class.set(0);
+        self.sweep_page.set(self.pages[0].get());
+        self.sweep_large.set(self.large.get());
+    }
+
+    // Sweeps the next page (or the next large object) which has not yet been swept, calling `visit`
+    // with the header of every object in it and whether that object was marked, and then clearing
+    // its mark bits.  `visit` may deallocate the object it is given, but nothing else.  Returns
+    // false without calling `visit` once there is nothing left to sweep.
+    pub(crate) unsafe fn sweep_step(
+        &self,
+        mut visit: impl FnMut(NonNull<GcBoxHeader>, bool),
+    ) -> bool {
+        while self.sweep_class.get() < SIZE_CLASSES.len() {
+            if let Some(page) = self.sweep_page.get() {
+                let header = page.as_ref();
+                self.sweep_page.set(header.next_page.get());
+                if !header.unswept.replace(false) {
+                    // Pages allocated during the sweep have nothing to sweep.
+                    continue;
+                }
+
+                for word in 0..BITMAP_WORDS {
+                    let mut allocated = header.allocated[word].get();
+                    let marked = header.marked[word].replace(0);
+                    while allocated != 0 {
+                        let bit = allocated.trailing_zeros() as usize;
+                        allocated &= allocated - 1;
+                        visit(
+                            object_header(page, word * 64 + bit),
+                            marked & (1 << bit) != 0,
+                        );
+                    }
+                }
+                return true;
+            } else {
+                let class = self.sweep_class.get() + 1;
+                self.sweep_class.set(class);
+                if class < SIZE_CLASSES.len() {
+                    self.sweep_page.set(self.pages[class].get());
+                }
+            }
+        }
+
+        while let Some(large) = self.sweep_large.get() {
+            let header = large.as_ref();
+            self.sweep_large.set(header.next.get());
+            if header.unswept.replace(false) {
+                visit(large_object_header(large), header.marked.replace(false));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Returns every page with no allocated slots to the allocator.  Must not be called while a
+    // sweep is in progress.
+    pub(crate) unsafe fn release_empty_pages(&self) {
+        for head in &self.pages {
+            let mut prev: Option<NonNull<PageHeader>> = None;
+            let mut next = head.get();
+            while let Some(page) = next {
+                let header = page.as_ref();
+                next = header.next_page.get();
+                if header.used.get() == 0 {
+                    match prev {
+                        Some(prev) => prev.as_ref().next_page.set(next),
+                        None => head.set(next),
+                    }
+                    if header.available.get() {
+                        self.unlink_available(page);
+                    }
+                    self.allocator.deallocate(page.cast(), page_layout());
+                } else {
+                    prev = Some(page);
+                }
+            }
+        }
+    }
+
+    unsafe fn allocate_slot(&self, class: usize, header_offset: usize) -> Option<NonNull<u8>> {
+        let kind = page_kind(header_offset);
+        let page = match self.available[class][kind].get() {
+            Some(page) => page,
+            None => {
+                let page = self.allocator.allocate(page_layout())?.cast::<PageHeader>();
+                page.as_ptr().write(PageHeader {
+                    class,
+                    header_offset,
+                    free: Cell::new(None),
+                    bump: Cell::new(HEADER_SIZE),
+                    used: Cell::new(0),
+                    unswept: Cell::new(false),
+                    next_page: Cell::new(self.pages[class].get()),
+                    available: Cell::new(false),
+                    prev: Cell::new(None),
+                    next: Cell::new(None),
+                    allocated: Default::default(),
+                    marked: Default::default(),
+                });
+                self.pages[class].set(Some(page));
+                self.push_available(page);
+                page
+            }
+        };
+
+        let header = page.as_ref();
+        debug_assert_eq!(header.header_offset, header_offset);
+        let slot_size = SIZE_CLASSES[class];
+
+        // Previously freed slots are reused first, otherwise slots that have never been used are
+        // handed out in address order.
+        let slot = if let Some(slot) = header.free.get() {
+            header.free.set(slot.as_ref().next);
+            slot.cast::<u8>()
+        } else {
+            let offset = header.bump.get();
+            debug_assert!(offset + slot_size <= PAGE_SIZE);
+            header.bump.set(offset + slot_size);
+            NonNull::new_unchecked(page.cast::<u8>().as_ptr().add(offset))
+        };
+        header.used.set(header.used.get() + 1);
+
+        // An object allocated into a page which is still waiting to be swept is marked, since
+        // nothing has had the chance to reach it yet.
+        let (word, bit) = bitmap_index(slot_index(page, slot));
+        header.allocated[word].set(header.allocated[word].get() | bit);
+        if header.unswept.get() {
+            header.marked[word].set(header.marked[word].get() | bit);
+        }
+
+        if header.free.get().is_none() && header.bump.get() + slot_size > PAGE_SIZE {
+            self.unlink_available(page);
+        }
+
+        Some(slot)
+    }
+
+    unsafe fn allocate_large(&self, layout: Layout, header_offset: usize) -> Option<NonNull<u8>> {
+        let (large_layout, offset) = large_layout(layout)?;
+        let large = self.allocator.allocate(large_layout)?.cast::<LargeHeader>();
+        large.as_ptr().write(LargeHeader {
+            header_offset: offset + header_offset,
+            marked: Cell::new(false),
+            unswept: Cell::new(false),
+            prev: Cell::new(None),
+            next: Cell::new(self.large.get()),
+        });
+        if let Some(next) = self.large.get() {
+            next.as_ref().prev.set(Some(large));
+        }
+        self.large.set(Some(large));
+        Some(NonNull::new_unchecked(
+            large.cast::<u8>().as_ptr().add(offset),
+        ))
+    }
+
+    unsafe fn push_available(&self, page: NonNull<PageHeader>) {
+        let header = page.as_ref();
+        let head = &self.available[header.class][page_kind(header.header_offset)];
+        header.available.set(true);
+        header.prev.set(None);
+        header.next.set(head.get());
+        if let Some(next) = head.get() {
+            next.as_ref().prev.set(Some(page));
+        }
+        head.set(Some(page));
+    }
+
+    unsafe fn unlink_available(&self, page: NonNull<PageHeader>) {
+        let header = page.as_ref();
+        debug_assert!(header.available.get());
+        header.available.set(false);
+        if let Some(next) = header.next.get() {
+            next.as_ref().prev.set(header.prev.get());
+        }
+        match header.prev.get() {
+            Some(prev) => prev.as_ref().next.set(header.next.get()),
+            None => {
+                self.available[header.class][page_kind(header.header_offset)].set(header.next.get())
+            }
+        }
+        header.prev.set(None);
+        header.next.set(None);
+    }
+}
+
+// Placed at the beginning of every page.
+struct PageHeader {
+    class: usize,
+    // The offset of the header of every object within its slot.
+    header_offset: usize,
+    // Slots which have been allocated and then freed.
+    free: Cell<Option<NonNull<FreeSlot>>>,
+    // The offset of the first slot which has never been allocated.
+    bump: Cell<usize>,
+    // The number of currently allocated slots.
+    used: Cell<usize>,
+    // Set on every page when a sweep begins, and cleared once the page has been swept.
+    unswept: Cell<bool>,
+    // The next page in the list of every page of this size class.
+    next_page: Cell<Option<NonNull<PageHeader>>>,
+    // Whether this page is in the `available` list for its size class and kind, along with its
+    // links in that list.
+    available: Cell<bool>,
+    prev: Cell<Option<NonNull<PageHeader>>>,
+    next: Cell<Option<NonNull<PageHeader>>>,
+    // One bit for every slot, set if the slot holds an object, and if that object has been marked
+    // during the current cycle.
+    allocated: [Cell<u64>; BITMAP_WORDS],
+    marked: [Cell<u64>; BITMAP_WORDS],
+}
+
+// Placed before every object in the large object space.
+struct LargeHeader {
+    // The offset of the object's header from the start of this large header.
+    header_offset: usize,
+    marked: Cell<bool>,
+    unswept: Cell<bool>,
+    prev: Cell<Option<NonNull<LargeHeader>>>,
+    next: Cell<Option<NonNull<LargeHeader>>>,
+}
+
+// Written into every slot once it is freed.
+struct FreeSlot {
+    next: Option<NonNull<FreeSlot>>,
+}
+
+enum Location {
+    Slot(NonNull<PageHeader>, usize),
+    Large(NonNull<LargeHeader>),
+}
+
+// Finds where the given allocation lives, objects are placed by their layout alone, so this always
+// agrees with `Heap::allocate`.
+unsafe fn locate(ptr: NonNull<u8>, layout: Layout) -> Location {
+    match size_class(layout) {
+        Some(_) => {
+            let page = page_of(ptr);
+            Location::Slot(page, slot_index(page, ptr))
+        }
+        None => {
+            let offset = large_layout(layout).unwrap().1;
+            Location::Large(NonNull::new_unchecked(
+                ptr.as_ptr().sub(offset) as *mut LargeHeader
+            ))
+        }
+    }
+}
+
+unsafe fn object_header(page: NonNull<PageHeader>, slot: usize) -> NonNull<GcBoxHeader> {
+    let header = page.as_ref();
+    let offset = HEADER_SIZE + slot * SIZE_CLASSES[header.class] + header.header_offset;
+    NonNull::new_unchecked(page.cast::<u8>().as_ptr().add(offset) as *mut GcBoxHeader)
+}
+
+unsafe fn large_object_header(large: NonNull<LargeHeader>) -> NonNull<GcBoxHeader> {
+    let offset = large.as_ref().header_offset;
+    NonNull::new_unchecked(large.cast::<u8>().as_ptr().add(offset) as *mut GcBoxHeader)
+}
+
+fn size_class(layout: Layout) -> Option<usize> {
+    if layout.align() > SLOT_ALIGN {
+        return None;
+    }
+    let class = SIZE_CLASSES.partition_point(|&size| size < layout.size());
+    if class < SIZE_CLASSES.len() {
+        Some(class)
+    } else {
+        None
+    }
+}
+
+fn page_kind(header_offset: usize) -> usize {
+    debug_assert!(
+        header_offset == 0 || (header_offset.is_power_of_two() && header_offset <= SLOT_ALIGN)
+    );
+    if header_offset == 0 {
+        0
+    } else {
+        header_offset.trailing_zeros() as usize
+    }
+}
+
+fn page_layout() -> Layout {
+    Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+// The layout of a large object's allocation including its large header, along with the offset of
+// the object within it.
+fn large_layout(layout: Layout) -> Option<(Layout, usize)> {
+    Layout::new::<LargeHeader>().extend(layout).ok()
+}
+
+fn page_of(ptr: NonNull<u8>) -> NonNull<PageHeader> {
+    unsafe { NonNull::new_unchecked((ptr.as_ptr() as usize & !(PAGE_SIZE - 1)) as *mut PageHeader) }
+}
+
+fn slot_index(page: NonNull<PageHeader>, ptr: NonNull<u8>) -> usize {
+    let class = unsafe { page.as_ref().class };
+    (ptr.as_ptr() as usize - page.as_ptr() as usize - HEADER_SIZE) / SIZE_CLASSES[class]
+}
+
+fn bitmap_index(slot: usize) -> (usize, u64) {
+    (slot / 64, 1 << (slot % 64))
+}
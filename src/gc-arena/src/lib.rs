@@ -8,26 +8,69 @@ extern crate alloc;
 #[doc(hidden)]
 pub use gc_arena_derive::*;
 
+mod allocator;
 mod arena;
+mod barrier;
+mod census;
 mod collect;
 mod collect_impl;
 mod context;
+mod dominators;
+mod dynamic_roots;
+mod ephemeron;
+mod finalization;
 mod gc;
 mod gc_cell;
+#[cfg(feature = "std")]
+mod gc_interner;
+mod gc_lock;
 mod gc_weak;
 mod gc_weak_cell;
+#[cfg(feature = "std")]
+mod gc_weak_map;
+mod gc_weak_set;
+mod heap;
+mod lock;
 mod no_drop;
+mod rootable;
+mod snapshot;
 mod static_collect;
+mod stats;
 mod types;
 
 pub use self::{
-    arena::{rootless_arena, ArenaParameters},
+    allocator::{GcAllocator, Global},
+    arena::{rootless_arena, Arena, ArenaParameters, CollectionEvent, HeapLimitExceeded},
+    barrier::{Unlock, Write},
+    census::{CensusChange, CensusDiff, CensusEntry, HeapCensus},
     collect::Collect,
-    context::{CollectionContext, Context, MutationContext},
+    context::{CollectionContext, CollectionPhase, MutationContext},
+    dominators::{DominatorTree, TypeSummary},
+    dynamic_roots::{DynamicRoot, DynamicRootSet},
+    ephemeron::EphemeronTable,
+    finalization::FinalizationQueue,
     gc::Gc,
     gc_cell::GcCell,
+    gc_lock::GcLock,
     gc_weak::GcWeak,
     gc_weak_cell::GcWeakCell,
+    gc_weak_set::{GcWeakSet, GcWeakSetIter},
+    lock::{Lock, RefLock},
     no_drop::MustNotImplDrop,
+    rootable::{Root, Rootable},
+    snapshot::{HeapEdge, HeapNode, HeapSnapshot},
     static_collect::StaticCollect,
+    stats::{CollectionSummary, CollectorStats, CycleStats, TypeStats},
+};
+
+#[cfg(feature = "std")]
+pub use self::{
+    gc_interner::GcInterner,
+    gc_weak_map::{GcWeakMap, GcWeakMapIter},
 };
+
+#[doc(hidden)]
+pub use self::types::GcBox as __GcBox;
+
+#[doc(hidden)]
+pub use self::rootable::__DynRootable;
@@ -0,0 +1,140 @@
+use core::cell::{BorrowError, Cell, Ref, RefCell};
+use core::fmt::{self, Debug};
+
+use crate::barrier::Unlock;
+use crate::collect::Collect;
+use crate::context::CollectionContext;
+
+/// A `Cell` which may be placed inside a garbage collected object, and may only be mutated through
+/// a `&Write<Lock<T>>`.
+///
+/// Unlike `GcLock`, a `Lock` is not a separate allocation, so one object may hold any number of
+/// them.  Call `Gc::write` on the object holding it and project to it with `field!` (or use the
+/// `unlock!` shorthand) to get access to the inner `Cell`.
+#[derive(Default)]
+#[repr(transparent)]
+pub struct Lock<T: ?Sized> {
+    cell: Cell<T>,
+}
+
+impl<T: Copy + Debug> Debug for Lock<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("Lock").field(&self.get()).finish()
+    }
+}
+
+impl<T: Copy> Clone for Lock<T> {
+    fn clone(&self) -> Lock<T> {
+        Lock::new(self.get())
+    }
+}
+
+impl<T> Lock<T> {
+    pub fn new(t: T) -> Lock<T> {
+        Lock { cell: Cell::new(t) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+}
+
+impl<T: Copy> Lock<T> {
+    pub fn get(&self) -> T {
+        self.cell.get()
+    }
+}
+
+impl<T: ?Sized> Lock<T> {
+    pub fn as_ptr(&self) -> *mut T {
+        self.cell.as_ptr()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+}
+
+impl<T: ?Sized> Unlock for Lock<T> {
+    type Unlocked = Cell<T>;
+
+    #[inline]
+    unsafe fn unlock_unchecked(&self) -> &Cell<T> {
+        &self.cell
+    }
+}
+
+unsafe impl<T: Collect> Collect for Lock<T> {
+    #[inline]
+    fn needs_trace() -> bool {
+        T::needs_trace()
+    }
+
+    #[inline]
+    fn trace(&self, cc: CollectionContext) {
+        // The contents can only be replaced during mutation, never while tracing.
+        unsafe { (*self.cell.as_ptr()).trace(cc) }
+    }
+}
+
+/// A `RefCell` which may be placed inside a garbage collected object, and may only be mutably
+/// borrowed through a `&Write<RefLock<T>>`.
+///
+/// This is the `RefCell` analogue of `Lock`, and the field-level counterpart of `GcCell`.
+#[derive(Default)]
+pub struct RefLock<T: ?Sized> {
+    cell: RefCell<T>,
+}
+
+impl<T: Debug + ?Sized> Debug for RefLock<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("RefLock").field(&&self.cell).finish()
+    }
+}
+
+impl<T> RefLock<T> {
+    pub fn new(t: T) -> RefLock<T> {
+        RefLock {
+            cell: RefCell::new(t),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.cell.into_inner()
+    }
+}
+
+impl<T: ?Sized> RefLock<T> {
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.cell.borrow()
+    }
+
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        self.cell.try_borrow()
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.cell.as_ptr()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cell.get_mut()
+    }
+}
+
+impl<T: ?Sized> Unlock for RefLock<T> {
+    type Unlocked = RefCell<T>;
+
+    #[inline]
+    unsafe fn unlock_unchecked(&self) -> &RefCell<T> {
+        &self.cell
+    }
+}
+
+unsafe impl<T: Collect + ?Sized> Collect for RefLock<T> {
+    #[inline]
+    fn trace(&self, cc: CollectionContext) {
+        cc.trace_ref_cell(&self.cell);
+    }
+}
@@ -0,0 +1,48 @@
+use core::marker::PhantomData;
+
+/// A `'static` type which names a type with a single `'gc` lifetime, so that it can be projected to
+/// any particular lifetime.
+///
+/// Types such as `Node<'gc>` cannot be named outside of a `mutate` call, where no `'gc` lifetime
+/// is available, so anything which must outlive a call (such as a `DynamicRoot` handle) names its
+/// contents with a `Rootable` type instead.  This trait should not normally be implemented by
+/// hand, use the `Rootable!` macro to produce an implementing type.
+pub trait Rootable<'gc>: 'static {
+    type Root: 'gc;
+}
+
+/// The type named by a `Rootable` type at the given lifetime.
+pub type Root<'gc, R> = <R as Rootable<'gc>>::Root;
+
+/// Produces a type implementing `Rootable` for every lifetime, given a type with a named lifetime.
+///
+/// ```
+/// # use gc_arena::{Collect, Gc, Root, Rootable};
+/// #[derive(Collect)]
+/// #[collect(no_drop)]
+/// struct Node<'gc> {
+///     next: Option<Gc<'gc, Node<'gc>>>,
+/// }
+///
+/// type NodeRoot = Rootable!['gc => Node<'gc>];
+///
+/// fn project<'gc>(node: Root<'gc, NodeRoot>) -> Node<'gc> {
+///     node
+/// }
+/// ```
+#[macro_export]
+macro_rules! Rootable {
+    ($gc:lifetime => $root:ty) => {
+        $crate::__DynRootable::<dyn for<$gc> $crate::Rootable<$gc, Root = $root>>
+    };
+}
+
+// Implements `Rootable` by deferring to a `dyn Rootable` type, which is the only way to write a type
+// which is generic over a lifetime.  Public but hidden, only so that it may be named by the
+// `Rootable!` macro.
+#[doc(hidden)]
+pub struct __DynRootable<T: ?Sized>(PhantomData<T>);
+
+impl<'gc, T: ?Sized + Rootable<'gc>> Rootable<'gc> for __DynRootable<T> {
+    type Root = <T as Rootable<'gc>>::Root;
+}
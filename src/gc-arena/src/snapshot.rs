@@ -0,0 +1,521 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::ptr::NonNull;
+
+use crate::census::HeapCensus;
+use crate::collect::Collect;
+use crate::dominators::DominatorTree;
+use crate::gc::Gc;
+use crate::gc_weak::GcWeak;
+use crate::types::GcBoxHeader;
+
+/// A snapshot of every object reachable from an arena's root, returned by `heap_snapshot`.
+///
+/// Every node in the snapshot is identified by its index in `nodes`.  The first node is always the
+/// root itself, every other node is a garbage collected object.  There is an edge from one node to
+/// another for every `Gc` pointer found while tracing it, and a weak edge for every `GcWeak`
+/// pointer whose target is also in the snapshot.
+///
+/// Taking a snapshot is a separate traversal from garbage collection, and does not disturb the
+/// collector's state.  A snapshot holds no pointers into the arena, so it may be kept and examined
+/// after the arena has changed, or written out with `write_dot` or `write_json`.
+#[derive(Clone, Debug, Default)]
+pub struct HeapSnapshot {
+    nodes: Vec<HeapNode>,
+    edges: Vec<HeapEdge>,
+    // Node indexes by the address of their value.
+    index: BTreeMap<usize, usize>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeapNode {
+    /// The name of the type of the object, as returned by `core::any::type_name`.  Objects created
+    /// with `unsize!` keep the name of the type they were allocated with.
+    pub type_name: &'static str,
+    /// The size in bytes of the object's allocation, including the collector's header.
+    pub size: usize,
+    /// The address of the object's value, the same as `Gc::as_ptr` returns.
+    pub address: usize,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HeapEdge {
+    pub from: usize,
+    pub to: usize,
+    pub weak: bool,
+}
+
+impl HeapSnapshot {
+    /// The index of the root node.
+    pub const ROOT: usize = 0;
+
+    pub fn nodes(&self) -> &[HeapNode] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[HeapEdge] {
+        &self.edges
+    }
+
+    /// The total size in bytes of every object in the snapshot, not including the root.
+    pub fn total_size(&self) -> usize {
+        self.nodes[1..].iter().map(|node| node.size).sum()
+    }
+
+    /// Returns the index of the node for the given object, if it was reachable when the snapshot
+    /// was taken.  This only compares addresses, so the object must not have been freed since.
+    pub fn find<T: Collect + ?Sized>(&self, gc: Gc<'_, T>) -> Option<usize> {
+        self.index
+            .get(&(Gc::as_ptr(gc) as *const u8 as usize))
+            .copied()
+    }
+
+    /// Returns the index of the node for the target of the given weak pointer, if it was reachable
+    /// when the snapshot was taken.
+    pub fn find_weak<T: Collect + ?Sized>(&self, weak: GcWeak<'_, T>) -> Option<usize> {
+        self.find(weak.inner)
+    }
+
+    /// Returns a shortest path of strong edges from the root to the given node, which is what keeps
+    /// the node alive, or `None` if it cannot be reached through strong edges alone.  The path
+    /// starts with `ROOT` and ends with `node`.  To find the path to a single object without taking
+    /// a snapshot, use `Arena::retaining_path` instead.
+    ///
+    /// ```
+    /// # use gc_arena::{Arena, ArenaParameters, Collect, Gc, Rootable};
+    /// #[derive(Collect)]
+    /// #[collect(no_drop)]
+    /// struct Root<'gc> {
+    ///     outer: Gc<'gc, Gc<'gc, i32>>,
+    /// }
+    ///
+    /// let arena = Arena::<Rootable!['gc => Root<'gc>]>::new(ArenaParameters::default(), |mc| Root {
+    ///     outer: Gc::allocate(mc, Gc::allocate(mc, 4)),
+    /// });
+    ///
+    /// arena.mutate(|_, root| {
+    ///     let snapshot = arena.heap_snapshot();
+    ///     let inner = snapshot.find(*root.outer).unwrap();
+    ///     let path = snapshot.retaining_path(inner).unwrap();
+    ///     let names: Vec<_> = path.iter().map(|&n| snapshot.nodes()[n].type_name).collect();
+    ///     assert_eq!(names[1..], ["gc_arena::gc::Gc<'_, i32>", "i32"]);
+    /// });
+    /// ```
+    pub fn retaining_path(&self, node: usize) -> Option<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        for edge in self.edges.iter().filter(|edge| !edge.weak) {
+            children[edge.from].push(edge.to);
+        }
+
+        // A breadth first search from the root, recording the parent each node was first reached
+        // from.
+        let mut parents = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        parents[Self::ROOT] = Some(Self::ROOT);
+        queue.push_back(Self::ROOT);
+        while let Some(next) = queue.pop_front() {
+            if next == node {
+                break;
+            }
+            for &child in &children[next] {
+                if parents[child].is_none() {
+                    parents[child] = Some(next);
+                    queue.push_back(child);
+                }
+            }
+        }
+
+        parents[node]?;
+        let mut path = vec![node];
+        let mut next = node;
+        while next != Self::ROOT {
+            next = parents[next].unwrap();
+            path.push(next);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Counts the objects in the snapshot by type.
+    pub fn census(&self) -> HeapCensus {
+        HeapCensus::new(self)
+    }
+
+    /// Computes the dominator tree of the snapshot, which gives the retained size of every node:
+    /// the amount of memory that would be freed if nothing else referenced it.
+    pub fn dominators(&self) -> DominatorTree {
+        DominatorTree::new(self)
+    }
+
+    /// Writes the snapshot as a Graphviz DOT graph, with weak edges drawn dashed.
+    pub fn write_dot<W: Write>(&self, w: &mut W) -> fmt::Result {
+        writeln!(w, "digraph heap {{")?;
+        writeln!(w, "    node [shape=box];")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            write!(w, "    n{} [label=\"", i)?;
+            if i == Self::ROOT {
+                write!(w, "(root)\\n")?;
+            }
+            write_escaped(w, node.type_name)?;
+            writeln!(w, "\\n{} bytes\\n{:#x}\"];", node.size, node.address)?;
+        }
+        for edge in &self.edges {
+            write!(w, "    n{} -> n{}", edge.from, edge.to)?;
+            if edge.weak {
+                write!(w, " [style=dashed]")?;
+            }
+            writeln!(w, ";")?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Writes the snapshot as JSON, in the `.heapsnapshot` format used by V8 heap profilers.
+    ///
+    /// Objects are named by their type name, and strong edges are numbered elements of the object
+    /// holding them.  The root is written as a synthetic node.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> fmt::Result {
+        const NODE_FIELDS: usize = 6;
+        const NODE_TYPE_SYNTHETIC: usize = 9;
+        const NODE_TYPE_OBJECT: usize = 3;
+        const EDGE_TYPE_ELEMENT: usize = 1;
+        const EDGE_TYPE_WEAK: usize = 6;
+
+        // The format requires edges to be listed in the order of the nodes they come from.
+        let mut edges_from = vec![Vec::new(); self.nodes.len()];
+        for edge in &self.edges {
+            edges_from[edge.from].push(*edge);
+        }
+
+        // Every type name is only written once, in the string table.
+        let mut strings = Vec::new();
+        let mut string_ids = BTreeMap::new();
+        let mut string_id = |s: &'static str| {
+            *string_ids.entry(s).or_insert_with(|| {
+                strings.push(s);
+                strings.len() - 1
+            })
+        };
+        let node_names: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| string_id(node.type_name))
+            .collect();
+        let weak_name = string_id("weak");
+
+        write!(
+            w,
+            "{{\"snapshot\":{{\"meta\":{{\
+             \"node_fields\":[\"type\",\"name\",\"id\",\"self_size\",\"edge_count\",\"trace_node_id\"],\
+             \"node_types\":[[\"hidden\",\"array\",\"string\",\"object\",\"code\",\"closure\",\
+             \"regexp\",\"number\",\"native\",\"synthetic\",\"concatenated string\",\
+             \"sliced string\",\"symbol\",\"bigint\"],\
+             \"string\",\"number\",\"number\",\"number\",\"number\"],\
+             \"edge_fields\":[\"type\",\"name_or_index\",\"to_node\"],\
+             \"edge_types\":[[\"context\",\"element\",\"property\",\"internal\",\"hidden\",\
+             \"shortcut\",\"weak\"],\"string_or_number\",\"node\"],\
+             \"trace_function_info_fields\":[],\"trace_node_fields\":[],\
+             \"sample_fields\":[],\"location_fields\":[]}},\
+             \"node_count\":{},\"edge_count\":{},\"trace_function_count\":0}},",
+            self.nodes.len(),
+            self.edges.len()
+        )?;
+
+        write!(w, "\"nodes\":[")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let node_type = if i == Self::ROOT {
+                NODE_TYPE_SYNTHETIC
+            } else {
+                NODE_TYPE_OBJECT
+            };
+            if i != 0 {
+                write!(w, ",")?;
+            }
+            write!(
+                w,
+                "{},{},{},{},{},0",
+                node_type,
+                node_names[i],
+                i * 2 + 1,
+                node.size,
+                edges_from[i].len()
+            )?;
+        }
+
+        write!(w, "],\"edges\":[")?;
+        let mut first = true;
+        for edges in &edges_from {
+            for (j, edge) in edges.iter().enumerate() {
+                if !first {
+                    write!(w, ",")?;
+                }
+                first = false;
+                let (edge_type, name) = if edge.weak {
+                    (EDGE_TYPE_WEAK, weak_name)
+                } else {
+                    (EDGE_TYPE_ELEMENT, j)
+                };
+                write!(w, "{},{},{}", edge_type, name, edge.to * NODE_FIELDS)?;
+            }
+        }
+
+        write!(
+            w,
+            "],\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[],\"locations\":[],\
+             \"strings\":["
+        )?;
+        for (i, s) in strings.iter().enumerate() {
+            if i != 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "\"")?;
+            write_escaped(w, s)?;
+            write!(w, "\"")?;
+        }
+        write!(w, "]}}")
+    }
+}
+
+// Escapes a string for use inside a quoted DOT label or JSON string, both of which accept the same
+// backslash escapes.
+fn write_escaped<W: Write>(w: &mut W, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+// Records the object graph while the collector traces it in snapshot mode, see
+// `Context::heap_snapshot`.
+pub(crate) struct SnapshotBuilder {
+    snapshot: HeapSnapshot,
+    // The object for every node but the root, by index, and every node index by the address of
+    // its object's header.
+    objects: Vec<NonNull<GcBoxHeader>>,
+    ids: BTreeMap<NonNull<GcBoxHeader>, usize>,
+    // The node currently being traced, and the next node to trace.
+    current: usize,
+    next: usize,
+    // Weak edges, which are only kept if their target turns out to be reachable.
+    weak: Vec<(usize, NonNull<GcBoxHeader>)>,
+}
+
+impl SnapshotBuilder {
+    pub(crate) fn new<R>(root: &R) -> SnapshotBuilder {
+        SnapshotBuilder {
+            snapshot: HeapSnapshot {
+                nodes: vec![HeapNode {
+                    type_name: core::any::type_name::<R>(),
+                    size: core::mem::size_of::<R>(),
+                    address: root as *const R as usize,
+                }],
+                edges: Vec::new(),
+                index: BTreeMap::new(),
+            },
+            objects: Vec::new(),
+            ids: BTreeMap::new(),
+            current: HeapSnapshot::ROOT,
+            next: 1,
+            weak: Vec::new(),
+        }
+    }
+
+    pub(crate) unsafe fn trace(&mut self, ptr: NonNull<GcBoxHeader>) {
+        let to = self.node(ptr);
+        self.snapshot.edges.push(HeapEdge {
+            from: self.current,
+            to,
+            weak: false,
+        });
+    }
+
+    pub(crate) fn trace_weak(&mut self, ptr: NonNull<GcBoxHeader>) {
+        self.weak.push((self.current, ptr));
+    }
+
+    pub(crate) fn is_visited(&self, ptr: NonNull<GcBoxHeader>) -> bool {
+        self.ids.contains_key(&ptr)
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.snapshot.nodes.len()
+    }
+
+    // Records edges from the given object, which must already have been visited, until the next
+    // call to `set_current` or `next_untraced`.
+    pub(crate) fn set_current(&mut self, ptr: NonNull<GcBoxHeader>) {
+        self.current = self.ids[&ptr];
+    }
+
+    // Returns the next object which has been visited but not yet traced, and records edges from it.
+    pub(crate) fn next_untraced(&mut self) -> Option<NonNull<GcBoxHeader>> {
+        let ptr = *self.objects.get(self.next - 1)?;
+        self.current = self.next;
+        self.next += 1;
+        Some(ptr)
+    }
+
+    pub(crate) fn finish(mut self) -> HeapSnapshot {
+        for (from, ptr) in self.weak {
+            if let Some(&to) = self.ids.get(&ptr) {
+                self.snapshot.edges.push(HeapEdge {
+                    from,
+                    to,
+                    weak: true,
+                });
+            }
+        }
+        self.snapshot
+    }
+
+    unsafe fn node(&mut self, ptr: NonNull<GcBoxHeader>) -> usize {
+        if let Some(&id) = self.ids.get(&ptr) {
+            return id;
+        }
+
+        let id = self.snapshot.nodes.len();
+        let address = GcBoxHeader::value_ptr(ptr) as usize;
+        self.snapshot.nodes.push(HeapNode {
+            type_name: GcBoxHeader::type_name(ptr),
+            size: GcBoxHeader::size(ptr),
+            address,
+        });
+        self.snapshot.index.insert(address, id);
+        self.objects.push(ptr);
+        self.ids.insert(ptr, id);
+        id
+    }
+}
+
+// Searches breadth first for a shortest path of strong edges from the root to a single object,
+// recording only the object that every visited object was first reached from, and stopping as soon
+// as the target is found.
+pub(crate) struct PathFinder {
+    root_type_name: &'static str,
+    target: NonNull<GcBoxHeader>,
+    // Every visited object along with the index of the object it was first reached from, or `None`
+    // if it was reached from the root, and the index of every visited object by its header.
+    objects: Vec<(NonNull<GcBoxHeader>, Option<usize>)>,
+    ids: BTreeMap<NonNull<GcBoxHeader>, usize>,
+    // The object currently being traced, and the next object to trace.
+    current: Option<usize>,
+    next: usize,
+    found: Option<usize>,
+}
+
+impl PathFinder {
+    pub(crate) fn new<R>(target: NonNull<GcBoxHeader>) -> PathFinder {
+        PathFinder {
+            root_type_name: core::any::type_name::<R>(),
+            target,
+            objects: Vec::new(),
+            ids: BTreeMap::new(),
+            current: None,
+            next: 0,
+            found: None,
+        }
+    }
+
+    fn trace(&mut self, ptr: NonNull<GcBoxHeader>) {
+        if self.ids.contains_key(&ptr) {
+            return;
+        }
+        let id = self.objects.len();
+        self.objects.push((ptr, self.current));
+        self.ids.insert(ptr, id);
+        if ptr == self.target {
+            self.found = Some(id);
+        }
+    }
+
+    // Returns the type name of every object on the path, starting with the root, or `None` if the
+    // target was not reached.
+    pub(crate) unsafe fn finish(self) -> Option<Vec<&'static str>> {
+        let mut path = Vec::new();
+        let mut next = Some(self.found?);
+        while let Some(id) = next {
+            let (ptr, parent) = self.objects[id];
+            path.push(GcBoxHeader::type_name(ptr));
+            next = parent;
+        }
+        path.push(self.root_type_name);
+        path.reverse();
+        Some(path)
+    }
+}
+
+// A traversal of every object reachable from the root which does not disturb the collector.  While
+// one is in progress, tracing reports every pointer to it rather than marking anything.
+pub(crate) enum HeapWalk {
+    Snapshot(SnapshotBuilder),
+    Path(PathFinder),
+}
+
+impl HeapWalk {
+    pub(crate) unsafe fn trace(&mut self, ptr: NonNull<GcBoxHeader>) {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.trace(ptr),
+            HeapWalk::Path(finder) => finder.trace(ptr),
+        }
+    }
+
+    pub(crate) fn trace_weak(&mut self, ptr: NonNull<GcBoxHeader>) {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.trace_weak(ptr),
+            // Weak pointers never keep anything alive.
+            HeapWalk::Path(_) => {}
+        }
+    }
+
+    pub(crate) fn is_visited(&self, ptr: NonNull<GcBoxHeader>) -> bool {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.is_visited(ptr),
+            HeapWalk::Path(finder) => finder.ids.contains_key(&ptr),
+        }
+    }
+
+    pub(crate) fn visited_count(&self) -> usize {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.node_count(),
+            HeapWalk::Path(finder) => finder.objects.len(),
+        }
+    }
+
+    // True once the walk has found everything it is looking for, even if there are more objects
+    // left to visit.
+    pub(crate) fn is_done(&self) -> bool {
+        match self {
+            HeapWalk::Snapshot(_) => false,
+            HeapWalk::Path(finder) => finder.found.is_some(),
+        }
+    }
+
+    // Reports pointers as coming from the given object, which must already have been visited,
+    // until the next call to `set_current` or `next_untraced`.
+    pub(crate) fn set_current(&mut self, ptr: NonNull<GcBoxHeader>) {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.set_current(ptr),
+            HeapWalk::Path(finder) => finder.current = Some(finder.ids[&ptr]),
+        }
+    }
+
+    // Returns the next object which has been visited but not yet traced, and reports pointers as
+    // coming from it.
+    pub(crate) fn next_untraced(&mut self) -> Option<NonNull<GcBoxHeader>> {
+        match self {
+            HeapWalk::Snapshot(builder) => builder.next_untraced(),
+            HeapWalk::Path(finder) => {
+                let &(ptr, _) = finder.objects.get(finder.next)?;
+                finder.current = Some(finder.next);
+                finder.next += 1;
+                Some(ptr)
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// Statistics about the state of an arena's garbage collector, returned by `stats`.
+///
+/// Counts are cumulative over the lifetime of the arena unless stated otherwise.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CollectorStats {
+    /// The number of objects currently allocated.  This includes unreachable objects which have
+    /// not been freed yet.
+    pub object_count: usize,
+    /// The total size in bytes of every currently allocated object, along with any external
+    /// memory they own.
+    pub total_allocated: usize,
+    /// The total size in bytes of memory owned by objects outside of the arena, as reported with
+    /// `MutationContext::set_external_size`.  This is included in `total_allocated`.
+    pub external_allocated: usize,
+    /// The number of completed full collection cycles.
+    pub cycles: u64,
+    /// The number of minor collections, only performed when generational collection is enabled.
+    pub minor_collections: u64,
+    /// The number of calls to `Gc::write_barrier`, including those made by `GcCell`, `GcLock` and
+    /// other internally mutable types.
+    pub write_barriers: u64,
+    /// The number of objects freed, by both full cycles and minor collections.
+    pub objects_freed: u64,
+    /// The total size in bytes of every object freed, by both full cycles and minor collections.
+    pub bytes_freed: u64,
+    /// Statistics for the last completed full collection cycle.
+    pub last_cycle: CycleStats,
+}
+
+/// Statistics for a single full collection cycle.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CycleStats {
+    /// The number of objects found to be reachable.
+    pub objects_marked: usize,
+    /// The total size in bytes of every object found to be reachable.
+    pub bytes_marked: usize,
+    /// The number of objects freed during the sweep phase.
+    pub objects_freed: usize,
+    /// The total size in bytes of every object freed during the sweep phase.
+    pub bytes_freed: usize,
+    /// The total size in bytes of every object which survived the sweep phase, used to decide how
+    /// long the collector sleeps before the next cycle.
+    pub bytes_remembered: usize,
+    /// The number of objects which were already marked and had to be traced again because a write
+    /// barrier fired for them while marking was in progress.
+    pub gray_again: usize,
+    /// The time spent performing collection work in the wake phase, which traces the root.
+    #[cfg(feature = "std")]
+    pub wake_time: Duration,
+    /// The time spent performing collection work in the propagate phase, which marks every
+    /// reachable object.
+    #[cfg(feature = "std")]
+    pub propagate_time: Duration,
+    /// The time spent performing collection work in the sweep phase, which frees every unreachable
+    /// object.
+    #[cfg(feature = "std")]
+    pub sweep_time: Duration,
+}
+
+/// A summary of the collection work performed by a single call to `collect_debt` or `collect_all`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CollectionSummary {
+    /// The amount of work performed, measured in bytes of objects either marked or freed.
+    pub work_done: f64,
+    /// The number of objects freed.
+    pub objects_freed: u64,
+    /// The total size in bytes of every object freed.
+    pub bytes_freed: u64,
+    /// The number of full collection cycles which were completed.
+    pub cycles_completed: u64,
+    /// The number of minor collections performed.
+    pub minor_collections: u64,
+}
+
+/// Allocation statistics for every object of a single type, returned by `type_stats`.
+///
+/// Types are identified by `core::any::type_name`, so distinct types which happen to share a name
+/// are counted together.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TypeStats {
+    pub type_name: &'static str,
+    /// The number of objects of this type currently allocated.  This includes unreachable objects
+    /// which have not been freed yet.
+    pub live_count: usize,
+    /// The total size in bytes of every object of this type currently allocated.
+    pub live_bytes: usize,
+    /// The number of objects of this type ever allocated.
+    pub allocated_count: u64,
+    /// The total size in bytes of every object of this type ever allocated.
+    pub allocated_bytes: u64,
+}
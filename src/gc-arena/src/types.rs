@@ -1,8 +1,10 @@
+use core::alloc::Layout;
 use core::cell::{Cell, UnsafeCell};
 use core::marker::PhantomData;
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 
 use crate::collect::Collect;
+use crate::context::CollectionContext;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub(crate) enum GcColor {
@@ -12,12 +14,197 @@ pub(crate) enum GcColor {
     Black,
 }
 
-pub(crate) struct GcBox<T: Collect + ?Sized> {
-    pub(crate) flags: GcFlags,
-    pub(crate) next: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
+// Public but hidden, only so that it may be named by the `unsize!` macro.
+#[doc(hidden)]
+#[repr(C)]
+pub struct GcBox<T: Collect + ?Sized> {
+    pub(crate) header: GcBoxHeader,
     pub(crate) value: UnsafeCell<T>,
 }
 
+// Every `GcBox` begins with a header, which holds everything the collector needs in order to manage
+// an object without knowing its type, so that a pointer to the header alone (rather than a fat
+// pointer) is enough to identify any object.  This is what allows unsized objects like slices,
+// which cannot be coerced to `dyn Collect`, to be allocated.
+pub(crate) struct GcBoxHeader {
+    pub(crate) flags: GcFlags,
+    vtable: &'static GcBoxVtable,
+}
+
+impl GcBoxHeader {
+    pub(crate) fn new<T: Collect>(flags: GcFlags) -> Self {
+        GcBoxHeader {
+            flags,
+            vtable: SizedVtable::<T>::VTABLE,
+        }
+    }
+
+    // The header for a box created with `slice_allocation_layout`, the length of the slice must be
+    // written immediately before the header.
+    pub(crate) fn new_slice<T: Collect>(flags: GcFlags) -> Self {
+        GcBoxHeader {
+            flags,
+            vtable: SliceVtable::<T>::VTABLE,
+        }
+    }
+
+    // The header for a `str`, which is otherwise allocated exactly like a `[u8]`.
+    pub(crate) fn new_str(flags: GcFlags) -> Self {
+        GcBoxHeader {
+            flags,
+            vtable: STR_VTABLE,
+        }
+    }
+
+    // Returns the start of the allocation holding the given object, along with its layout.
+    #[inline]
+    pub(crate) unsafe fn allocation(ptr: NonNull<GcBoxHeader>) -> (NonNull<u8>, Layout) {
+        (ptr.as_ref().vtable.allocation)(ptr)
+    }
+
+    // The total size of the allocation holding the given object.
+    #[inline]
+    pub(crate) unsafe fn size(ptr: NonNull<GcBoxHeader>) -> usize {
+        Self::allocation(ptr).1.size()
+    }
+
+    #[inline]
+    pub(crate) unsafe fn trace_value(ptr: NonNull<GcBoxHeader>, cc: CollectionContext) {
+        (ptr.as_ref().vtable.trace_value)(ptr, cc)
+    }
+
+    #[inline]
+    pub(crate) unsafe fn drop_value(ptr: NonNull<GcBoxHeader>) {
+        (ptr.as_ref().vtable.drop_value)(ptr)
+    }
+
+    // The address of the value held by the given object, the same as `Gc::as_ptr` would return.
+    #[inline]
+    pub(crate) unsafe fn value_ptr(ptr: NonNull<GcBoxHeader>) -> *const u8 {
+        (ptr.as_ref().vtable.value_ptr)(ptr)
+    }
+
+    // The name of the type that the given object was allocated with, for debugging.
+    #[inline]
+    pub(crate) unsafe fn type_name(ptr: NonNull<GcBoxHeader>) -> &'static str {
+        (ptr.as_ref().vtable.type_name)()
+    }
+
+    // Panics if the given object has already been freed and is only being kept in quarantine.
+    #[cfg(feature = "poison")]
+    #[inline]
+    pub(crate) unsafe fn assert_not_poisoned(ptr: NonNull<GcBoxHeader>) {
+        if ptr.as_ref().flags.poisoned() {
+            panic!(
+                "use of freed object of type `{}`, which was not traced",
+                Self::type_name(ptr)
+            );
+        }
+    }
+}
+
+struct GcBoxVtable {
+    allocation: unsafe fn(NonNull<GcBoxHeader>) -> (NonNull<u8>, Layout),
+    trace_value: unsafe fn(NonNull<GcBoxHeader>, CollectionContext),
+    drop_value: unsafe fn(NonNull<GcBoxHeader>),
+    value_ptr: unsafe fn(NonNull<GcBoxHeader>) -> *const u8,
+    type_name: fn() -> &'static str,
+}
+
+const STR_VTABLE: &GcBoxVtable = &GcBoxVtable {
+    type_name: || "str",
+    ..*SliceVtable::<u8>::VTABLE
+};
+
+struct SizedVtable<T>(PhantomData<T>);
+
+impl<T: Collect> SizedVtable<T> {
+    const VTABLE: &'static GcBoxVtable = &GcBoxVtable {
+        allocation: Self::allocation,
+        trace_value: Self::trace_value,
+        drop_value: Self::drop_value,
+        value_ptr: Self::value_ptr,
+        type_name: core::any::type_name::<T>,
+    };
+
+    unsafe fn allocation(ptr: NonNull<GcBoxHeader>) -> (NonNull<u8>, Layout) {
+        (ptr.cast(), Layout::new::<GcBox<T>>())
+    }
+
+    unsafe fn trace_value(ptr: NonNull<GcBoxHeader>, cc: CollectionContext) {
+        (*ptr.cast::<GcBox<T>>().as_ref().value.get()).trace(cc)
+    }
+
+    unsafe fn drop_value(ptr: NonNull<GcBoxHeader>) {
+        ptr::drop_in_place(ptr.cast::<GcBox<T>>().as_ref().value.get())
+    }
+
+    unsafe fn value_ptr(ptr: NonNull<GcBoxHeader>) -> *const u8 {
+        ptr.cast::<GcBox<T>>().as_ref().value.get() as *const u8
+    }
+}
+
+struct SliceVtable<T>(PhantomData<T>);
+
+impl<T: Collect> SliceVtable<T> {
+    const VTABLE: &'static GcBoxVtable = &GcBoxVtable {
+        allocation: Self::allocation,
+        trace_value: Self::trace_value,
+        drop_value: Self::drop_value,
+        value_ptr: Self::value_ptr,
+        type_name: core::any::type_name::<[T]>,
+    };
+
+    unsafe fn slice_box(ptr: NonNull<GcBoxHeader>) -> NonNull<GcBox<[T]>> {
+        let len = *ptr.cast::<usize>().as_ptr().sub(1);
+        slice_box_ptr(ptr.cast(), len)
+    }
+
+    unsafe fn allocation(ptr: NonNull<GcBoxHeader>) -> (NonNull<u8>, Layout) {
+        let len = *ptr.cast::<usize>().as_ptr().sub(1);
+        let (layout, offset) = slice_allocation_layout::<T>(len);
+        (
+            NonNull::new_unchecked(ptr.cast::<u8>().as_ptr().sub(offset)),
+            layout,
+        )
+    }
+
+    unsafe fn trace_value(ptr: NonNull<GcBoxHeader>, cc: CollectionContext) {
+        (*Self::slice_box(ptr).as_ref().value.get()).trace(cc)
+    }
+
+    unsafe fn drop_value(ptr: NonNull<GcBoxHeader>) {
+        ptr::drop_in_place(Self::slice_box(ptr).as_ref().value.get())
+    }
+
+    unsafe fn value_ptr(ptr: NonNull<GcBoxHeader>) -> *const u8 {
+        Self::slice_box(ptr).as_ref().value.get() as *const u8
+    }
+}
+
+// Slices are allocated with their length stored immediately before the `GcBox`, so that the header
+// can be the same size for every object.  Returns the layout of the entire allocation, along with
+// the offset of the `GcBox` within it.
+pub(crate) fn slice_allocation_layout<T>(len: usize) -> (Layout, usize) {
+    let value = Layout::array::<T>(len).expect("slice too large to allocate");
+    let (gc_box, _) = Layout::new::<GcBoxHeader>()
+        .extend(value)
+        .expect("slice too large to allocate");
+    let (allocation, offset) = Layout::new::<usize>()
+        .extend(gc_box.pad_to_align())
+        .expect("slice too large to allocate");
+    (allocation.pad_to_align(), offset)
+}
+
+// Creates a pointer to a `GcBox` holding a slice of the given length.
+pub(crate) fn slice_box_ptr<T: Collect>(ptr: NonNull<u8>, len: usize) -> NonNull<GcBox<[T]>> {
+    unsafe {
+        NonNull::new_unchecked(
+            ptr::slice_from_raw_parts_mut(ptr.as_ptr() as *mut T, len) as *mut GcBox<[T]>
+        )
+    }
+}
+
 pub(crate) struct GcFlags(Cell<u8>);
 
 impl GcFlags {
@@ -65,6 +252,26 @@ impl GcFlags {
         self.0.get() & 0x10 != 0x0
     }
 
+    // Whether this object is in the nursery and has not yet survived a minor collection.
+    #[inline]
+    pub(crate) fn young(&self) -> bool {
+        self.0.get() & 0x20 != 0x0
+    }
+
+    // Whether this object is in the remembered set of old objects which have been mutated since the
+    // last minor collection.
+    #[inline]
+    pub(crate) fn remembered(&self) -> bool {
+        self.0.get() & 0x40 != 0x0
+    }
+
+    // Whether this object has been freed and its memory poisoned, but is still quarantined.
+    #[cfg(feature = "poison")]
+    #[inline]
+    pub(crate) fn poisoned(&self) -> bool {
+        self.0.get() & 0x80 != 0x0
+    }
+
     #[inline]
     pub(crate) fn set_needs_trace(&self, needs_trace: bool) {
         self.0
@@ -82,6 +289,25 @@ impl GcFlags {
         self.0
             .set((self.0.get() & !0x10) | if alive { 0x10 } else { 0x0 });
     }
+
+    #[inline]
+    pub(crate) fn set_young(&self, young: bool) {
+        self.0
+            .set((self.0.get() & !0x20) | if young { 0x20 } else { 0x0 });
+    }
+
+    #[inline]
+    pub(crate) fn set_remembered(&self, remembered: bool) {
+        self.0
+            .set((self.0.get() & !0x40) | if remembered { 0x40 } else { 0x0 });
+    }
+
+    #[cfg(feature = "poison")]
+    #[inline]
+    pub(crate) fn set_poisoned(&self, poisoned: bool) {
+        self.0
+            .set((self.0.get() & !0x80) | if poisoned { 0x80 } else { 0x0 });
+    }
 }
 
 // Phantom type that holds a lifetime and ensures that it is invariant.
@@ -1,10 +1,20 @@
 #[cfg(feature = "std")]
 use rand::distributions::Distribution;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
 #[cfg(feature = "std")]
 use std::collections::HashMap;
+use std::ptr::NonNull;
 use std::rc::Rc;
 
-use gc_arena::{make_arena, unsafe_empty_collect, ArenaParameters, Collect, Gc, GcCell, GcWeak};
+use gc_arena::{
+    field, unlock, unsafe_empty_collect, unsize, Arena, ArenaParameters, Collect, CollectionEvent,
+    CollectionPhase, DynamicRootSet, EphemeronTable, FinalizationQueue, Gc, GcAllocator, GcCell,
+    GcLock, GcWeak, GcWeakSet, HeapEdge, HeapSnapshot, Lock, MutationContext, RefLock, Rootable,
+    TypeSummary,
+};
+#[cfg(feature = "std")]
+use gc_arena::{GcInterner, GcWeakMap};
 
 #[test]
 fn simple_allocation() {
@@ -14,7 +24,7 @@ fn simple_allocation() {
         test: Gc<'gc, i32>,
     }
 
-    make_arena!(TestArena, TestRoot);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
     let arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
         test: Gc::allocate(mc, 42),
@@ -25,6 +35,68 @@ fn simple_allocation() {
     });
 }
 
+mod roots {
+    use gc_arena::{Collect, Gc};
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    pub struct Pair<'gc, T: 'gc + Collect> {
+        pub first: Gc<'gc, T>,
+        pub second: Gc<'gc, T>,
+    }
+}
+
+#[test]
+fn generic_root() {
+    use gc_arena::Root;
+
+    // Arenas may be held and collected by code which knows nothing about their roots.
+    fn collect_all<R>(arenas: &mut [Arena<R>]) -> usize
+    where
+        R: for<'a> Rootable<'a>,
+        for<'a> Root<'a, R>: Collect,
+    {
+        arenas
+            .iter_mut()
+            .map(|arena| {
+                arena.collect_all();
+                arena.stats().object_count
+            })
+            .sum()
+    }
+
+    type IntArena = Arena<Rootable!['gc => roots::Pair<'gc, i32>]>;
+    type StrArena = Arena<Rootable!['gc => roots::Pair<'gc, Gc<'gc, str>>]>;
+
+    let mut ints: Vec<IntArena> = (0..3)
+        .map(|i| {
+            Arena::new(ArenaParameters::default(), |mc| roots::Pair {
+                first: Gc::allocate(mc, i),
+                second: Gc::allocate(mc, i * 2),
+            })
+        })
+        .collect();
+    let mut strs: Vec<StrArena> = vec![Arena::new(ArenaParameters::default(), |mc| roots::Pair {
+        first: Gc::allocate(mc, Gc::from_str(mc, "first")),
+        second: Gc::allocate(mc, Gc::from_str(mc, "second")),
+    })];
+
+    for arena in &ints {
+        arena.mutate(|mc, _| {
+            Gc::allocate(mc, 0);
+        });
+    }
+    assert_eq!(collect_all(&mut ints), 6);
+    assert_eq!(collect_all(&mut strs), 4);
+
+    ints[2].mutate(|_, root| assert_eq!((*root.first, *root.second), (2, 4)));
+    strs[0].mutate_root(|mc, root| {
+        root.second = Gc::allocate(mc, Gc::from_str(mc, "replaced"));
+    });
+    assert_eq!(collect_all(&mut strs), 4);
+    strs[0].mutate(|_, root| assert_eq!((&**root.first, &**root.second), ("first", "replaced")));
+}
+
 #[test]
 fn weak_allocation() {
     #[derive(Collect)]
@@ -34,7 +106,7 @@ fn weak_allocation() {
         weak: GcWeak<'gc, i32>,
     }
 
-    make_arena!(TestArena, TestRoot);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
     let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
         let test = Gc::allocate(mc, 42);
@@ -80,7 +152,7 @@ fn repeated_allocation_deallocation() {
     #[derive(Collect)]
     #[collect(no_drop)]
     struct TestRoot<'gc>(GcCell<'gc, HashMap<i32, Gc<'gc, (i32, RefCounter)>>>);
-    make_arena!(TestArena, TestRoot);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
     let r = RefCounter(Rc::new(()));
 
@@ -128,7 +200,7 @@ fn all_dropped() {
     #[derive(Collect)]
     #[collect(no_drop)]
     struct TestRoot<'gc>(GcCell<'gc, Vec<Gc<'gc, RefCounter>>>);
-    make_arena!(TestArena, TestRoot);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
     let r = RefCounter(Rc::new(()));
 
@@ -155,7 +227,7 @@ fn all_garbage_collected() {
     #[derive(Collect)]
     #[collect(no_drop)]
     struct TestRoot<'gc>(GcCell<'gc, Vec<Gc<'gc, RefCounter>>>);
-    make_arena!(TestArena, TestRoot);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
     let r = RefCounter(Rc::new(()));
 
@@ -178,77 +250,2051 @@ fn all_garbage_collected() {
 }
 
 #[test]
-fn derive_collect() {
-    #[allow(unused)]
+fn finalization() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
     #[derive(Collect)]
     #[collect(no_drop)]
-    struct Test1<'gc> {
-        a: i32,
-        b: Gc<'gc, i32>,
+    struct Object<'gc> {
+        counter: RefCounter,
+        child: Gc<'gc, RefCounter>,
     }
 
-    #[allow(unused)]
     #[derive(Collect)]
     #[collect(no_drop)]
-    struct Test2 {
-        a: i32,
-        b: i32,
+    struct TestRoot<'gc> {
+        queue: FinalizationQueue<'gc, Object<'gc>>,
+        objects: GcCell<'gc, Vec<Gc<'gc, Object<'gc>>>>,
     }
 
-    #[allow(unused)]
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        queue: FinalizationQueue::new(mc),
+        objects: GcCell::allocate(mc, Vec::new()),
+    });
+
+    arena.mutate(|mc, root| {
+        for _ in 0..10 {
+            let object = Gc::allocate(
+                mc,
+                Object {
+                    counter: r.clone(),
+                    child: Gc::allocate(mc, r.clone()),
+                },
+            );
+            root.queue.register(mc, object);
+            root.objects.write(mc).push(object);
+        }
+    });
+
+    arena.collect_all();
+    arena.mutate(|_, root| assert!(root.queue.is_empty()));
+    assert_eq!(Rc::strong_count(&r.0), 21);
+
+    arena.mutate(|mc, root| {
+        root.objects.write(mc).truncate(4);
+    });
+    arena.collect_all();
+
+    // Unreachable objects are resurrected along with everything they point to.
+    assert_eq!(Rc::strong_count(&r.0), 21);
+    arena.mutate(|_, root| {
+        assert_eq!(root.queue.len(), 6);
+        while let Some(object) = root.queue.pop() {
+            assert_eq!(Rc::strong_count(&object.child.0), 21);
+        }
+    });
+
+    // Once taken out of the queue, objects are no longer registered and are freed normally.
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 9);
+    arena.mutate(|_, root| assert!(root.queue.is_empty()));
+
+    arena.mutate(|mc, root| {
+        root.objects.write(mc).clear();
+    });
+    arena.collect_all();
+    arena.mutate(|_, root| {
+        assert_eq!(root.queue.len(), 4);
+        while root.queue.pop().is_some() {}
+    });
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn finalization_queue_in_finalized_object() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    // The inner queue is only reachable from the holder, which is itself only reachable once it
+    // has been resurrected by the outer queue.
     #[derive(Collect)]
     #[collect(no_drop)]
-    enum Test3<'gc> {
-        B(Gc<'gc, i32>),
-        A(i32),
+    struct Holder<'gc> {
+        queue: FinalizationQueue<'gc, RefCounter>,
     }
 
-    #[allow(unused)]
     #[derive(Collect)]
     #[collect(no_drop)]
-    enum Test4 {
-        A(i32),
+    struct TestRoot<'gc> {
+        queue: FinalizationQueue<'gc, Holder<'gc>>,
     }
 
-    #[allow(unused)]
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        queue: FinalizationQueue::new(mc),
+    });
+
+    arena.mutate(|mc, root| {
+        let holder = Gc::allocate(
+            mc,
+            Holder {
+                queue: FinalizationQueue::new(mc),
+            },
+        );
+        holder.queue.register(mc, Gc::allocate(mc, r.clone()));
+        root.queue.register(mc, holder);
+    });
+
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 2);
+    arena.mutate(|_, root| {
+        assert_eq!(root.queue.len(), 1);
+        let holder = root.queue.pop().unwrap();
+        assert_eq!(holder.queue.len(), 1);
+        assert_eq!(Rc::strong_count(&holder.queue.pop().unwrap().0), 2);
+    });
+
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn ephemerons() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
     #[derive(Collect)]
     #[collect(no_drop)]
-    struct Test5(Gc<'static, i32>);
+    struct Value<'gc> {
+        counter: RefCounter,
+        // Both a reference back to the key of this value's entry, and to the key of the next entry
+        // in a chain.
+        key: Gc<'gc, i32>,
+        next: Option<Gc<'gc, i32>>,
+    }
 
-    #[allow(unused)]
     #[derive(Collect)]
     #[collect(no_drop)]
-    struct Test6(i32);
+    struct TestRoot<'gc> {
+        table: EphemeronTable<'gc, i32, Value<'gc>>,
+        keys: GcCell<'gc, Vec<Gc<'gc, i32>>>,
+    }
 
-    assert_eq!(Test1::needs_trace(), true);
-    assert_eq!(Test2::needs_trace(), false);
-    assert_eq!(Test3::needs_trace(), true);
-    assert_eq!(Test4::needs_trace(), false);
-    assert_eq!(Test5::needs_trace(), true);
-    assert_eq!(Test6::needs_trace(), false);
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
 
-    struct NoImpl;
+    let r = RefCounter(Rc::new(()));
 
-    #[allow(unused)]
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        table: EphemeronTable::new(mc),
+        keys: GcCell::allocate(mc, Vec::new()),
+    });
+
+    arena.mutate(|mc, root| {
+        let keys = (0..10).map(|i| Gc::allocate(mc, i)).collect::<Vec<_>>();
+        for i in 0..10 {
+            root.table.insert(
+                mc,
+                keys[i],
+                Value {
+                    counter: r.clone(),
+                    key: keys[i],
+                    next: keys.get(i + 1).copied(),
+                },
+            );
+        }
+        root.keys.write(mc).extend_from_slice(&keys[0..2]);
+        root.keys.write(mc).extend_from_slice(&keys[5..6]);
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+
+    // The entries reachable by following the chain from each held key are still alive, the rest
+    // have been removed.
+    assert_eq!(Rc::strong_count(&r.0), 11);
+    arena.mutate(|_, root| {
+        assert_eq!(root.table.len(), 10);
+        for &key in root.keys.read().iter() {
+            let value = root.table.get(key).unwrap();
+            assert!(Gc::ptr_eq(value.key, key));
+        }
+    });
+
+    arena.mutate(|mc, root| {
+        let mut keys = root.keys.write(mc);
+        keys.remove(2);
+        assert!(root.table.remove(keys[1]).is_some());
+    });
+    arena.collect_all();
+    arena.collect_all();
+
+    assert_eq!(Rc::strong_count(&r.0), 2);
+    arena.mutate(|_, root| {
+        assert_eq!(root.table.len(), 1);
+        assert!(root.table.contains_key(root.keys.read()[0]));
+        assert!(!root.table.contains_key(root.keys.read()[1]));
+    });
+
+    arena.mutate(|mc, root| root.keys.write(mc).clear());
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+    arena.mutate(|_, root| assert!(root.table.is_empty()));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn weak_collections() {
     #[derive(Collect)]
     #[collect(no_drop)]
-    struct Test7 {
-        #[collect(require_static)]
-        field: NoImpl,
+    struct TestRoot<'gc> {
+        map: GcWeakMap<'gc, String, i32>,
+        set: GcWeakSet<'gc, i32>,
+        held: GcCell<'gc, Vec<Gc<'gc, i32>>>,
     }
 
-    #[allow(unused)]
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        map: GcWeakMap::new(mc),
+        set: GcWeakSet::new(mc),
+        held: GcCell::allocate(mc, Vec::new()),
+    });
+
+    arena.mutate(|mc, root| {
+        for i in 0..100 {
+            let gc = Gc::allocate(mc, i);
+            root.map.insert(mc, i.to_string(), gc);
+            root.set.insert(mc, gc);
+            if i % 10 == 0 {
+                root.held.write(mc).push(gc);
+            }
+        }
+        assert_eq!(root.map.iter(mc).count(), 100);
+        assert_eq!(root.set.iter(mc).count(), 100);
+    });
+
+    arena.collect_all();
+
+    arena.mutate(|mc, root| {
+        assert_eq!(root.map.len(), 10);
+        assert_eq!(root.set.len(), 10);
+        assert_eq!(*root.map.get(mc, "40").unwrap(), 40);
+        assert!(root.map.get(mc, "41").is_none());
+        for (key, gc) in root.map.iter(mc) {
+            assert_eq!(key.parse::<i32>().unwrap(), *gc);
+            assert!(root.set.contains(gc));
+        }
+        assert_eq!(root.set.iter(mc).map(|gc| *gc).sum::<i32>(), 450);
+
+        root.held.write(mc).retain(|gc| **gc < 50);
+    });
+
+    // Objects which have only just become unreachable are skipped before they are pruned.
+    let mut done = false;
+    while !done {
+        arena.mutate(|mc, root| {
+            Gc::allocate(mc, 0);
+            assert!(root.map.iter(mc).count() <= 10);
+            done = root.map.len() == 5 && root.set.len() == 5;
+            if done {
+                assert_eq!(root.map.iter(mc).count(), 5);
+                assert_eq!(root.set.iter(mc).count(), 5);
+            }
+        });
+        arena.collect_debt();
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn generational_collection() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
     #[derive(Collect)]
     #[collect(no_drop)]
-    enum Test8 {
-        First {
-            #[collect(require_static)]
-            field: NoImpl,
+    struct TestRoot<'gc> {
+        map: GcCell<'gc, HashMap<i32, Gc<'gc, (i32, RefCounter)>>>,
+        weak: GcCell<'gc, Vec<GcWeak<'gc, (i32, RefCounter)>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(
+        ArenaParameters::default().set_nursery_size(Some(1024)),
+        |mc| TestRoot {
+            map: GcCell::allocate(mc, HashMap::new()),
+            weak: GcCell::allocate(mc, Vec::new()),
         },
+    );
+
+    let key_range = rand::distributions::Uniform::from(0..1000);
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..200 {
+        arena.mutate(|mc, root| {
+            let mut map = root.map.write(mc);
+            let mut weak = root.weak.write(mc);
+            weak.clear();
+            for _ in 0..10 {
+                let i = key_range.sample(&mut rng);
+                let gc = Gc::allocate(mc, (i, r.clone()));
+                weak.push(Gc::downgrade(gc));
+                if let Some(old) = map.insert(i, gc) {
+                    assert_eq!(old.0, i);
+                }
+            }
+
+            for _ in 0..10 {
+                let i = key_range.sample(&mut rng);
+                if let Some(old) = map.remove(&i) {
+                    assert_eq!(old.0, i);
+                }
+            }
+
+            // Plenty of garbage that never escapes the nursery.
+            for i in 0..100 {
+                weak.push(Gc::downgrade(Gc::allocate(mc, (i, r.clone()))));
+            }
+        });
+
+        arena.collect_debt();
+
+        arena.mutate(|mc, root| {
+            for weak in root.weak.read().iter() {
+                if let Some(gc) = weak.upgrade(mc) {
+                    assert!(Rc::strong_count(&gc.1 .0) > 1);
+                }
+            }
+        });
     }
 
-    assert_eq!(Test7::needs_trace(), false);
-    assert_eq!(Test8::needs_trace(), false);
+    arena.collect_all();
+    arena.collect_all();
+
+    let live_size = arena.mutate(|_, root| root.map.read().len());
+    assert_eq!(Rc::strong_count(&r.0), live_size + 1);
+}
+
+#[test]
+fn custom_allocator() {
+    #[derive(Default)]
+    struct CountingAllocator {
+        live: Cell<usize>,
+        bytes: Cell<usize>,
+    }
+
+    unsafe impl GcAllocator for CountingAllocator {
+        fn allocate(&self, layout: Layout) -> Option<NonNull<u8>> {
+            self.live.set(self.live.get() + 1);
+            self.bytes.set(self.bytes.get() + layout.size());
+            NonNull::new(unsafe { System.alloc(layout) })
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            self.bytes.set(self.bytes.get() - layout.size());
+            System.dealloc(ptr.as_ptr(), layout)
+        }
+    }
+
+    type Large = ([u64; 32], [u64; 32], [u64; 32], [u64; 32], [u64; 32]);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        small: GcCell<'gc, Vec<Gc<'gc, i32>>>,
+        large: GcCell<'gc, Option<Gc<'gc, Large>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let allocator = Rc::new(CountingAllocator::default());
+
+    // Quarantined objects would delay pages being released.
+    let parameters = ArenaParameters::default();
+    #[cfg(feature = "poison")]
+    let parameters = parameters.set_quarantine_cycles(0);
+
+    let mut arena = TestArena::new_in(parameters, allocator.clone(), |mc| TestRoot {
+        small: GcCell::allocate(mc, Vec::new()),
+        large: GcCell::allocate(mc, None),
+    });
+    let initial_live = allocator.live.get();
+    let initial_bytes = allocator.bytes.get();
+    assert!(initial_live > 0);
+
+    // Small objects are packed into pages rather than being allocated individually.
+    arena.mutate(|mc, root| {
+        for i in 0..10000 {
+            root.small.write(mc).push(Gc::allocate(mc, i));
+        }
+    });
+    assert!(allocator.live.get() > initial_live);
+    assert!(allocator.live.get() < initial_live + 10000);
+    assert!(allocator.bytes.get() >= initial_bytes + 10000 * std::mem::size_of::<i32>());
+
+    // Once they are all freed, every page they occupied is released.
+    arena.mutate(|mc, root| root.small.write(mc).clear());
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(allocator.live.get(), initial_live);
+    assert_eq!(allocator.bytes.get(), initial_bytes);
+
+    // Large objects are allocated individually.
+    arena.mutate(|mc, root| {
+        *root.large.write(mc) = Some(Gc::allocate(
+            mc,
+            ([0; 32], [0; 32], [0; 32], [0; 32], [0; 32]),
+        ));
+    });
+    assert_eq!(allocator.live.get(), initial_live + 1);
+    assert!(allocator.bytes.get() >= initial_bytes + std::mem::size_of::<Large>());
+
+    arena.mutate(|mc, root| *root.large.write(mc) = None);
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(allocator.live.get(), initial_live);
+    assert_eq!(allocator.bytes.get(), initial_bytes);
+
+    drop(arena);
+    assert_eq!(allocator.live.get(), 0);
+    assert_eq!(allocator.bytes.get(), 0);
+}
+
+#[test]
+fn incremental_sweep() {
+    // Slices of this are placed into their own kind of page, since their length is stored at a
+    // different offset than other slices.
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    #[repr(align(16))]
+    struct Wide(u64);
+    unsafe_empty_collect!(Wide);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        live: GcCell<'gc, Vec<Gc<'gc, [u64; 4]>>>,
+        weak: GcCell<'gc, Vec<GcWeak<'gc, [u64; 4]>>>,
+        added: GcCell<'gc, Vec<Gc<'gc, [u64; 4]>>>,
+        wide: Gc<'gc, [Wide]>,
+        large: Gc<'gc, [u64]>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let phase = Rc::new(Cell::new(CollectionPhase::Sleep));
+    let parameters = ArenaParameters::default().set_collection_hook({
+        let phase = phase.clone();
+        move |event, _| {
+            if let CollectionEvent::PhaseChanged { to, .. } = event {
+                phase.set(to);
+            }
+        }
+    });
+
+    let mut arena = TestArena::new(parameters, |mc| TestRoot {
+        live: GcCell::allocate(mc, Vec::new()),
+        weak: GcCell::allocate(mc, Vec::new()),
+        added: GcCell::allocate(mc, Vec::new()),
+        wide: Gc::from_slice(mc, &[Wide(1), Wide(2)]),
+        large: Gc::from_slice(mc, &[7; 1000]),
+    });
+
+    arena.mutate(|mc, root| {
+        for i in 0..5000 {
+            let object = Gc::allocate(mc, [i; 4]);
+            if i % 5 == 0 {
+                root.weak.write(mc).push(Gc::downgrade(object));
+            }
+            root.live.write(mc).push(object);
+        }
+    });
+    arena.collect_all();
+
+    // The remaining live objects are interleaved with garbage, spread across many pages.  Freeing
+    // them does not allocate, so the collector is still asleep and the next cycle can be run a
+    // little at a time, allocating all the while.
+    arena.mutate(|mc, root| {
+        let mut live = root.live.write(mc);
+        let mut i = 0;
+        live.retain(|_| {
+            i += 1;
+            (i - 1) % 10 == 0
+        });
+    });
+    let mut sweep_steps = 0;
+    while phase.get() != CollectionPhase::Sweep {
+        arena.mutate(|mc, _| {
+            Gc::allocate(mc, [0u64; 4]);
+        });
+        arena.collect_debt();
+    }
+    while phase.get() == CollectionPhase::Sweep {
+        sweep_steps += 1;
+        arena.mutate(|mc, root| {
+            // Live objects may be upgraded whether or not their page has been swept yet.
+            for (i, weak) in root.weak.read().iter().enumerate() {
+                if i % 2 == 0 {
+                    assert_eq!(weak.upgrade(mc).unwrap()[0], i as u64 * 5);
+                }
+            }
+            for _ in 0..10 {
+                let i = root.added.read().len() as u64;
+                root.added.write(mc).push(Gc::allocate(mc, [i; 4]));
+            }
+        });
+        arena.collect_debt();
+    }
+    assert!(sweep_steps > 1);
+
+    // Objects allocated during the sweep survive it, whichever page they were placed into.
+    arena.mutate(|mc, root| {
+        for (i, object) in root.added.read().iter().enumerate() {
+            assert_eq!(object[0], i as u64);
+        }
+        for (i, weak) in root.weak.read().iter().enumerate() {
+            assert_eq!(weak.upgrade(mc).is_some(), i % 2 == 0);
+        }
+        assert_eq!(&*root.wide, &[Wide(1), Wide(2)]);
+        assert!(root.large.iter().all(|&n| n == 7));
+    });
+
+    arena.collect_all();
+    arena.mutate(|_, root| {
+        assert_eq!(root.live.read().len(), 500);
+        for (i, object) in root.added.read().iter().enumerate() {
+            assert_eq!(object[0], i as u64);
+        }
+        assert_eq!(&*root.wide, &[Wide(1), Wide(2)]);
+    });
+}
+
+#[test]
+fn unsized_allocation() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    trait Named: Collect {
+        fn name(&self) -> &str;
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Item<'gc> {
+        name: Gc<'gc, str>,
+        counter: RefCounter,
+    }
+
+    impl<'gc> Named for Item<'gc> {
+        fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        items: GcCell<'gc, Option<Gc<'gc, [Gc<'gc, Item<'gc>>]>>>,
+        named: GcCell<'gc, Vec<Gc<'gc, dyn Named + 'gc>>>,
+        weak: GcCell<'gc, Vec<GcWeak<'gc, dyn Named + 'gc>>>,
+        numbers: GcCell<'gc, Option<Gc<'gc, [u16]>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        items: GcCell::allocate(mc, None),
+        named: GcCell::allocate(mc, Vec::new()),
+        weak: GcCell::allocate(mc, Vec::new()),
+        numbers: GcCell::allocate(mc, None),
+    });
+
+    arena.mutate(|mc, root| {
+        let items = Gc::from_iter(
+            mc,
+            (0..10).map(|i| {
+                Gc::allocate(
+                    mc,
+                    Item {
+                        name: Gc::from_str(mc, &format!("item {}", i)),
+                        counter: r.clone(),
+                    },
+                )
+            }),
+        );
+        *root.items.write(mc) = Some(items);
+
+        for &item in items.iter() {
+            let named = unsize!(item => dyn Named);
+            root.weak
+                .write(mc)
+                .push(unsize!(Gc::downgrade(item) => dyn Named));
+            root.named.write(mc).push(named);
+        }
+
+        let numbers: Gc<[u16]> = unsize!(Gc::allocate(mc, [1, 2, 3]) => [u16]);
+        *root.numbers.write(mc) = Some(numbers);
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 11);
+
+    arena.mutate(|mc, root| {
+        let items = root.items.read().unwrap();
+        assert_eq!(items.len(), 10);
+        for (i, item) in items.iter().enumerate() {
+            assert_eq!(&*item.name, format!("item {}", i));
+            assert_eq!(root.named.read()[i].name(), format!("item {}", i));
+            let weak = root.weak.read()[i].upgrade(mc).unwrap();
+            assert!(Gc::ptr_eq(weak, root.named.read()[i]));
+        }
+        assert_eq!(&*root.numbers.read().unwrap(), &[1, 2, 3]);
+
+        *root.items.write(mc) = None;
+        root.named.write(mc).truncate(5);
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 6);
+
+    arena.mutate(|mc, root| {
+        for (i, weak) in root.weak.read().iter().enumerate() {
+            assert_eq!(weak.upgrade(mc).is_some(), i < 5);
+        }
+        root.named.write(mc).clear();
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn gc_lock() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    type Slot<'gc> = GcLock<'gc, Option<Gc<'gc, (i32, RefCounter)>>>;
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        slots: Vec<Slot<'gc>>,
+        count: GcLock<'gc, i32>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        slots: (0..100).map(|_| GcLock::allocate(mc, None)).collect(),
+        count: GcLock::allocate(mc, 0),
+    });
+
+    let slot_range = rand::distributions::Uniform::from(0..100);
+    let mut rng = rand::thread_rng();
+    let mut expected = vec![None; 100];
+
+    for i in 0..2000 {
+        arena.mutate(|mc, root| {
+            let index = slot_range.sample(&mut rng);
+            let slot = root.slots[index];
+            if i % 3 == 0 {
+                if slot.take(mc).is_some() {
+                    root.count.set(mc, root.count.get() - 1);
+                }
+                expected[index] = None;
+            } else {
+                let old = slot.replace(mc, Some(Gc::allocate(mc, (i, r.clone()))));
+                if old.is_none() {
+                    root.count.set(mc, root.count.get() + 1);
+                }
+                expected[index] = Some(i);
+            }
+        });
+
+        // Collect a little at a time, so that slots are set while the collector is propagating.
+        arena.collect_debt();
+
+        arena.mutate(|_, root| {
+            for (slot, &expected) in root.slots.iter().zip(&expected) {
+                assert_eq!(slot.get().map(|gc| gc.0), expected);
+            }
+        });
+    }
+
+    arena.collect_all();
+    arena.collect_all();
+
+    let live = arena.mutate(|_, root| {
+        let live = root
+            .slots
+            .iter()
+            .filter(|slot| slot.get().is_some())
+            .count();
+        assert_eq!(live as i32, root.count.get());
+        live
+    });
+    assert_eq!(Rc::strong_count(&r.0), live + 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn field_locks() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Pair<'gc>(Lock<Option<Gc<'gc, i32>>>, Lock<Option<Gc<'gc, i32>>>);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        first: Lock<Option<Gc<'gc, i32>>>,
+        pair: Pair<'gc>,
+        list: RefLock<Vec<Gc<'gc, i32>>>,
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        node: Gc<'gc, Node<'gc>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        node: Gc::allocate(
+            mc,
+            Node {
+                first: Lock::new(None),
+                pair: Pair(Lock::new(None), Lock::new(None)),
+                list: RefLock::new(Vec::new()),
+            },
+        ),
+    });
+
+    let op_range = rand::distributions::Uniform::from(0..4);
+    let mut rng = rand::thread_rng();
+    let mut expected = (None, None, None, Vec::new());
+
+    for i in 0..2000 {
+        arena.mutate(|mc, root| {
+            let write = Gc::write(mc, root.node);
+            let gc = Gc::allocate(mc, i);
+            let value = Some(gc);
+            match op_range.sample(&mut rng) {
+                0 => {
+                    unlock!(write, Node, first).set(value);
+                    expected.0 = Some(i);
+                }
+                1 => {
+                    let pair = field!(write, Node, pair);
+                    unlock!(pair, Pair, 0).set(value);
+                    expected.1 = Some(i);
+                }
+                2 => {
+                    let pair = field!(write, Node, pair);
+                    unlock!(pair, Pair, 1).set(value);
+                    expected.2 = Some(i);
+                }
+                _ => {
+                    let mut list = unlock!(write, Node, list).borrow_mut();
+                    if list.len() == 10 {
+                        list.remove(0);
+                        expected.3.remove(0);
+                    }
+                    list.push(gc);
+                    expected.3.push(i);
+                }
+            }
+        });
+
+        // Collect a little at a time, so that fields are set while the collector is propagating.
+        arena.collect_debt();
+
+        arena.mutate(|_, root| {
+            let node = &*root.node;
+            assert_eq!(node.first.get().map(|gc| *gc), expected.0);
+            assert_eq!(node.pair.0.get().map(|gc| *gc), expected.1);
+            assert_eq!(node.pair.1.get().map(|gc| *gc), expected.2);
+            let list = node.list.borrow();
+            assert!(list.iter().map(|gc| **gc).eq(expected.3.iter().copied()));
+        });
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn interner() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        strings: GcInterner<'gc, str>,
+        pairs: GcInterner<'gc, (i32, i32)>,
+        kept: Vec<Gc<'gc, str>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        let strings = GcInterner::new(mc);
+        let pairs = GcInterner::new(mc);
+        let mut kept = Vec::new();
+        for i in 0..100 {
+            let name = format!("name{}", i % 10);
+            let a = strings.intern_str(mc, &name);
+            let b = strings.intern_str(mc, &name);
+            assert!(Gc::ptr_eq(a, b));
+            assert_eq!(&*a, name);
+            if i < 10 && i % 2 == 0 {
+                kept.push(a);
+            }
+
+            let p = pairs.intern(mc, (i % 5, 0));
+            assert!(Gc::ptr_eq(p, pairs.intern(mc, (i % 5, 0))));
+        }
+        assert!(!Gc::ptr_eq(
+            strings.intern_str(mc, "name0"),
+            strings.intern_str(mc, "name1")
+        ));
+        assert_eq!(strings.len(), 10);
+        assert_eq!(pairs.len(), 5);
+
+        TestRoot {
+            strings,
+            pairs,
+            kept,
+        }
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+
+    arena.mutate(|mc, root| {
+        // Only the strings that are still referenced remain interned.
+        assert_eq!(root.strings.len(), 5);
+        assert!(root.pairs.is_empty());
+        for kept in &root.kept {
+            assert!(Gc::ptr_eq(root.strings.get(mc, &**kept).unwrap(), *kept));
+            assert!(Gc::ptr_eq(root.strings.intern_str(mc, kept), *kept));
+        }
+        assert!(root.strings.get(mc, "name1").is_none());
+        assert_eq!(&*root.strings.intern_str(mc, "name1"), "name1");
+        assert_eq!(root.strings.len(), 6);
+    });
+}
+
+#[test]
+fn collector_stats() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        kept: GcCell<'gc, Vec<Gc<'gc, i32>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        kept: GcCell::allocate(mc, (0..10).map(|i| Gc::allocate(mc, i)).collect()),
+    });
+
+    arena.mutate(|mc, root| {
+        for i in 0..100 {
+            Gc::allocate(mc, i);
+        }
+        for i in 0..3 {
+            root.kept.write(mc).push(Gc::allocate(mc, i));
+        }
+    });
+
+    let before = arena.stats();
+    assert_eq!(before.object_count, 114);
+    assert_eq!(before.write_barriers, 3);
+    assert_eq!(before.cycles, 0);
+
+    let summary = arena.collect_all();
+    let after = arena.stats();
+    assert_eq!(summary.cycles_completed, 1);
+    assert_eq!(summary.minor_collections, 0);
+    assert_eq!(summary.objects_freed, 100);
+    assert_eq!(
+        summary.bytes_freed,
+        (before.total_allocated - after.total_allocated) as u64
+    );
+    assert!(summary.work_done > 0.0);
+
+    assert_eq!(after.object_count, 14);
+    assert_eq!(after.cycles, 1);
+    assert_eq!(after.objects_freed, 100);
+    assert_eq!(after.last_cycle.objects_marked, 14);
+    assert_eq!(after.last_cycle.objects_freed, 100);
+    assert_eq!(after.last_cycle.bytes_freed as u64, summary.bytes_freed);
+    assert_eq!(after.last_cycle.bytes_marked, after.total_allocated);
+    assert_eq!(after.last_cycle.bytes_remembered, after.total_allocated);
+
+    arena.mutate(|mc, _| {
+        Gc::allocate(mc, 0);
+    });
+
+    let summary = arena.collect_all();
+    assert_eq!(summary.cycles_completed, 1);
+    assert_eq!(summary.objects_freed, 1);
+    assert_eq!(arena.stats().cycles, 2);
+    assert_eq!(
+        arena.stats().last_cycle.bytes_remembered,
+        arena.total_allocated()
+    );
+}
+
+#[test]
+fn external_memory() {
+    const BITMAP_SIZE: usize = 50 * 1024 * 1024;
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(require_static)]
+    struct Bitmap(Vec<u8>);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        bitmap: GcCell<'gc, Option<Gc<'gc, Bitmap>>>,
+        weak: GcCell<'gc, Option<GcWeak<'gc, Bitmap>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        bitmap: GcCell::allocate(mc, None),
+        weak: GcCell::allocate(mc, None),
+    });
+    arena.collect_all();
+    let base = arena.total_allocated();
+
+    // Reporting a large buffer wakes the collector and charges allocation debt for it.
+    arena.mutate(|mc, root| {
+        let bitmap = Gc::allocate(mc, Bitmap(Vec::new()));
+        mc.set_external_size(bitmap, BITMAP_SIZE);
+        assert_eq!(mc.external_size(bitmap), BITMAP_SIZE);
+        *root.bitmap.write(mc) = Some(bitmap);
+    });
+    assert!(arena.total_allocated() > base + BITMAP_SIZE);
+    assert_eq!(arena.stats().external_allocated, BITMAP_SIZE);
+    assert!(arena.allocation_debt() > BITMAP_SIZE as f64);
+
+    // Sizes are replaced rather than added to.
+    arena.mutate(|mc, root| {
+        let bitmap = root.bitmap.read().unwrap();
+        mc.set_external_size(bitmap, BITMAP_SIZE / 2);
+        assert_eq!(mc.external_size(bitmap), BITMAP_SIZE / 2);
+    });
+    assert_eq!(arena.stats().external_allocated, BITMAP_SIZE / 2);
+    assert!(arena.total_allocated() < base + BITMAP_SIZE);
+
+    // The size is released once the object is dropped, even if it is kept around for a weak
+    // pointer.
+    arena.mutate(|mc, root| {
+        let bitmap = root.bitmap.write(mc).take().unwrap();
+        *root.weak.write(mc) = Some(Gc::downgrade(bitmap));
+    });
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.stats().external_allocated, 0);
+    arena.mutate(|mc, root| assert!(root.weak.read().unwrap().upgrade(mc).is_none()));
+    arena.mutate(|mc, root| *root.weak.write(mc) = None);
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.total_allocated(), base);
+}
+
+#[test]
+fn heap_limit() {
+    const MAX_HEAP_SIZE: usize = 64 * 1024;
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(require_static)]
+    struct Block([u8; 1024]);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        blocks: GcCell<'gc, Vec<Gc<'gc, Block>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let refused = Rc::new(Cell::new(0));
+    let parameters = ArenaParameters::default()
+        .set_max_heap_size(Some(MAX_HEAP_SIZE))
+        .set_heap_pressure_hook({
+            let refused = refused.clone();
+            move |error| {
+                assert!(error.total_allocated + error.requested > error.max_heap_size);
+                refused.set(refused.get() + 1);
+            }
+        });
+    let mut arena = TestArena::new(parameters, |mc| TestRoot {
+        blocks: GcCell::allocate(mc, Vec::new()),
+    });
+
+    // Allocate until the limit is reached, then drop everything.
+    let error = arena.mutate(|mc, root| loop {
+        match Gc::try_allocate(mc, Block([0; 1024])) {
+            Ok(block) => root.blocks.write(mc).push(block),
+            Err(error) => {
+                assert!(GcCell::try_allocate(mc, ()).is_ok());
+                assert!(GcCell::try_allocate(mc, Block([0; 1024])).is_err());
+                root.blocks.write(mc).clear();
+                break error;
+            }
+        }
+    });
+    assert_eq!(refused.get(), 2);
+    assert_eq!(error.max_heap_size, MAX_HEAP_SIZE);
+    assert!(error.requested >= 1024);
+    assert!(arena.total_allocated() <= MAX_HEAP_SIZE);
+    assert!(error.to_string().contains("maximum heap size"));
+
+    // The refused allocation makes the next collection an emergency one, which frees every
+    // unreachable block regardless of allocation debt.
+    let summary = arena.collect_debt();
+    assert!(summary.objects_freed >= (MAX_HEAP_SIZE / 1024 - 2) as u64);
+    assert!(arena.total_allocated() < 4096);
+    arena.mutate(|mc, root| {
+        root.blocks
+            .write(mc)
+            .push(Gc::try_allocate(mc, Block([0; 1024])).unwrap());
+    });
+
+    // Slices and strings are limited in the same way.
+    arena.mutate(|mc, _| {
+        let bytes = vec![0u8; MAX_HEAP_SIZE];
+        assert!(Gc::try_from_slice(mc, &bytes).is_err());
+        assert!(Gc::try_from_iter(mc, bytes.iter().copied()).is_err());
+        assert!(Gc::try_from_str(mc, &"x".repeat(MAX_HEAP_SIZE)).is_err());
+        assert_eq!(*Gc::try_from_slice(mc, &[1, 2, 3]).unwrap(), [1, 2, 3]);
+        assert_eq!(&*Gc::try_from_str(mc, "small").unwrap(), "small");
+    });
+    assert_eq!(refused.get(), 5);
+
+    // Infallible allocation panics instead.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        arena.mutate(|mc, _| {
+            for _ in 0..MAX_HEAP_SIZE / 1024 {
+                Gc::allocate(mc, Block([0; 1024]));
+            }
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(refused.get(), 6);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        arena.mutate(|mc, _| Gc::from_str(mc, &"x".repeat(MAX_HEAP_SIZE)).len())
+    }));
+    assert!(result.is_err());
+    assert_eq!(refused.get(), 7);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn type_stats() {
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(require_static)]
+    struct Big([u8; 256]);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        bigs: GcCell<'gc, Vec<Gc<'gc, Big>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        bigs: GcCell::allocate(mc, Vec::new()),
+    });
+
+    arena.mutate(|mc, root| {
+        for _ in 0..3 {
+            root.bigs.write(mc).push(Gc::allocate(mc, Big([0; 256])));
+        }
+        Gc::from_str(mc, "unreachable");
+    });
+
+    let find = |arena: &TestArena, name: &str| {
+        arena
+            .type_stats()
+            .into_iter()
+            .find(|stats| stats.type_name.ends_with(name))
+            .unwrap()
+    };
+
+    let big = find(&arena, "::Big");
+    let big_size = big.live_bytes / 3;
+    assert!(big_size >= 256);
+    assert_eq!(big.live_count, 3);
+    assert_eq!(big.allocated_count, 3);
+    assert_eq!(arena.type_stats()[0], big);
+    assert_eq!(find(&arena, "str").live_count, 1);
+    assert_eq!(
+        arena
+            .type_stats()
+            .iter()
+            .map(|s| s.live_bytes)
+            .sum::<usize>(),
+        arena.total_allocated()
+    );
+
+    arena.mutate(|mc, root| {
+        root.bigs.write(mc).pop();
+    });
+    arena.collect_all();
+    arena.collect_all();
+
+    let big = find(&arena, "::Big");
+    assert_eq!(big.live_count, 2);
+    assert_eq!(big.live_bytes, 2 * big_size);
+    assert_eq!(big.allocated_count, 3);
+    assert_eq!(big.allocated_bytes, 3 * big_size as u64);
+    let str_stats = find(&arena, "str");
+    assert_eq!((str_stats.live_count, str_stats.live_bytes), (0, 0));
+    assert_eq!(str_stats.allocated_count, 1);
+}
+
+// A long linked list, which takes many calls to `collect_debt` to finish marking.
+#[derive(Collect)]
+#[collect(no_drop)]
+struct Chain<'gc> {
+    next: Option<Gc<'gc, Chain<'gc>>>,
+}
+
+impl<'gc> Chain<'gc> {
+    fn new(mc: MutationContext<'gc, '_>, len: usize) -> Gc<'gc, Chain<'gc>> {
+        let mut chain = Gc::allocate(mc, Chain { next: None });
+        for _ in 1..len {
+            chain = Gc::allocate(mc, Chain { next: Some(chain) });
+        }
+        chain
+    }
+}
+
+#[cfg(feature = "verify-barriers")]
+#[test]
+#[should_panic(expected = "missing write barrier")]
+fn verify_barriers() {
+    // Mutating this through a shared reference without calling `Gc::write_barrier` is unsound.
+    struct Holder<'gc> {
+        cell: Cell<Option<Gc<'gc, i32>>>,
+    }
+
+    unsafe impl<'gc> Collect for Holder<'gc> {
+        fn trace(&self, cc: gc_arena::CollectionContext) {
+            self.cell.get().trace(cc)
+        }
+    }
+
+    // The holder is traced before the chain, and the chain takes many calls to `collect_debt` to
+    // finish marking, so the holder is mutated while it is black.
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        chain: Gc<'gc, Chain<'gc>>,
+        holder: Gc<'gc, Holder<'gc>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        chain: Chain::new(mc, 1000),
+        holder: Gc::allocate(
+            mc,
+            Holder {
+                cell: Cell::new(None),
+            },
+        ),
+    });
+
+    for i in 0..10000 {
+        arena.mutate(|mc, root| root.holder.cell.set(Some(Gc::allocate(mc, i))));
+        arena.collect_debt();
+    }
+}
+
+#[test]
+fn mutate_root() {
+    // Records its value once dropped, which only happens once it has been found unreachable.
+    #[derive(Collect)]
+    #[collect(require_static)]
+    struct Tracked {
+        value: i32,
+        dropped: Rc<RefCell<Vec<i32>>>,
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.dropped.borrow_mut().push(self.value);
+        }
+    }
+
+    // The chain takes many calls to `collect_debt` to finish marking, so the root is replaced
+    // with newly allocated objects many times during each cycle.
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        chain: Gc<'gc, Chain<'gc>>,
+        latest: Option<Gc<'gc, Tracked>>,
+        history: Vec<Gc<'gc, Tracked>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let dropped = Rc::new(RefCell::new(Vec::new()));
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        chain: Chain::new(mc, 1000),
+        latest: None,
+        history: Vec::new(),
+    });
+
+    for i in 0..10000 {
+        arena.mutate_root(|mc, root| {
+            let latest = Gc::allocate(
+                mc,
+                Tracked {
+                    value: i,
+                    dropped: dropped.clone(),
+                },
+            );
+            root.latest = Some(latest);
+            if i % 100 == 0 {
+                root.history.push(latest);
+            }
+        });
+        arena.collect_debt();
+        assert!(!dropped.borrow().contains(&i));
+    }
+    assert!(arena.stats().cycles > 0);
+
+    arena.collect_all();
+    arena.mutate(|_, root| {
+        assert_eq!(root.latest.unwrap().value, 9999);
+        for (i, tracked) in root.history.iter().enumerate() {
+            assert_eq!(tracked.value, i as i32 * 100);
+        }
+    });
+    let dropped = dropped.borrow();
+    assert!(!dropped
+        .iter()
+        .any(|value| value % 100 == 0 || *value == 9999));
+}
+
+#[test]
+fn dynamic_roots() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        value: i32,
+        next: Option<Gc<'gc, Node<'gc>>>,
+    }
+
+    type NodeRoot = Rootable!['gc => Node<'gc>];
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        roots: DynamicRootSet<'gc>,
+        weak: GcCell<'gc, Option<GcWeak<'gc, Node<'gc>>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let new_arena = || {
+        TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+            roots: DynamicRootSet::new(mc),
+            weak: GcCell::allocate(mc, None),
+        })
+    };
+
+    let mut arena = new_arena();
+    let handle = arena.mutate(|mc, root| {
+        let tail = Gc::allocate(
+            mc,
+            Node {
+                value: 2,
+                next: None,
+            },
+        );
+        let head = Gc::allocate(
+            mc,
+            Node {
+                value: 1,
+                next: Some(tail),
+            },
+        );
+        *root.weak.write(mc) = Some(Gc::downgrade(tail));
+        root.roots.stash::<NodeRoot>(mc, head)
+    });
+
+    arena.collect_all();
+    arena.collect_all();
+    arena.mutate(|_, root| {
+        assert_eq!(root.roots.len(), 1);
+        let head = root.roots.fetch(&handle);
+        assert_eq!(head.value, 1);
+        assert_eq!(head.next.unwrap().value, 2);
+    });
+
+    // The object stays alive as long as any clone of the handle does.
+    let cloned = handle.clone();
+    drop(handle);
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        assert_eq!(root.roots.fetch(&cloned).value, 1);
+        assert!(root.weak.read().unwrap().upgrade(mc).is_some());
+    });
+
+    // Handles may not be used with another arena's root set.
+    let other = new_arena();
+    let foreign = other.mutate(|mc, root| {
+        root.roots.stash::<NodeRoot>(
+            mc,
+            Gc::allocate(
+                mc,
+                Node {
+                    value: 3,
+                    next: None,
+                },
+            ),
+        )
+    });
+    arena.mutate(|_, root| {
+        assert!(root.roots.contains(&cloned));
+        assert!(!root.roots.contains(&foreign));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            root.roots.fetch(&foreign);
+        }));
+        assert!(result.is_err());
+    });
+    drop(other);
+    drop(foreign);
+
+    drop(cloned);
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        assert!(root.roots.is_empty());
+        assert!(root.weak.read().unwrap().upgrade(mc).is_none());
+    });
+}
+
+#[test]
+fn collection_hook() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        test: Gc<'gc, i32>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let parameters = ArenaParameters::default().set_collection_hook({
+        let events = events.clone();
+        move |event, stats| events.borrow_mut().push((event, stats.cycles))
+    });
+
+    let mut arena = TestArena::new(parameters, |mc| TestRoot {
+        test: Gc::allocate(mc, 42),
+    });
+    arena.mutate(|mc, _| {
+        Gc::allocate(mc, 0);
+    });
+
+    arena.collect_all();
+    assert_eq!(
+        events.borrow_mut().drain(..).collect::<Vec<_>>(),
+        vec![
+            (CollectionEvent::CycleStarted, 0),
+            (
+                CollectionEvent::PhaseChanged {
+                    from: CollectionPhase::Wake,
+                    to: CollectionPhase::Propagate
+                },
+                0
+            ),
+            (
+                CollectionEvent::PhaseChanged {
+                    from: CollectionPhase::Propagate,
+                    to: CollectionPhase::Sweep
+                },
+                0
+            ),
+            (
+                CollectionEvent::PhaseChanged {
+                    from: CollectionPhase::Sweep,
+                    to: CollectionPhase::Sleep
+                },
+                0
+            ),
+            (CollectionEvent::CycleCompleted, 1),
+        ]
+    );
+
+    arena.collect_all();
+    let events = events.borrow();
+    assert_eq!(
+        events[0],
+        (
+            CollectionEvent::PhaseChanged {
+                from: CollectionPhase::Sleep,
+                to: CollectionPhase::Wake
+            },
+            1
+        )
+    );
+    assert_eq!(events[1], (CollectionEvent::CycleStarted, 1));
+    assert_eq!(events.last(), Some(&(CollectionEvent::CycleCompleted, 2)));
+}
+
+#[test]
+fn stress_mode() {
+    // Forgets to trace its pointer, so the pointed to object will be freed by the next full cycle.
+    #[allow(unused)]
+    struct Broken<'gc> {
+        hidden: Gc<'gc, i32>,
+    }
+
+    unsafe impl<'gc> Collect for Broken<'gc> {
+        fn trace(&self, _cc: gc_arena::CollectionContext) {}
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        broken: GcCell<'gc, Option<Gc<'gc, Broken<'gc>>>>,
+        hidden: GcCell<'gc, Option<GcWeak<'gc, i32>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default().set_stress(true), |mc| TestRoot {
+        broken: GcCell::allocate(mc, None),
+        hidden: GcCell::allocate(mc, None),
+    });
+
+    for _ in 0..10 {
+        let summary = arena.collect_debt();
+        assert_eq!(summary.cycles_completed, 1);
+    }
+
+    arena.mutate(|mc, root| {
+        let hidden = Gc::allocate(mc, 4);
+        *root.broken.write(mc) = Some(Gc::allocate(mc, Broken { hidden }));
+        *root.hidden.write(mc) = Some(Gc::downgrade(hidden));
+    });
+    assert_eq!(arena.stats().cycles, 10);
+
+    // Without stress mode, there would not be enough allocation debt for a single call to finish a
+    // cycle.
+    arena.collect_debt();
+    assert_eq!(arena.stats().cycles, 11);
+    arena.mutate(|mc, root| {
+        assert!(root.hidden.read().unwrap().upgrade(mc).is_none());
+        assert!(root.broken.read().is_some());
+    });
+}
+
+#[cfg(feature = "poison")]
+#[test]
+fn poison() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Forgets to trace both of its pointers, so they are left dangling once collected.
+    struct Broken<'gc> {
+        strong: Gc<'gc, i32>,
+        weak: GcWeak<'gc, i32>,
+    }
+
+    unsafe impl<'gc> Collect for Broken<'gc> {
+        fn trace(&self, _cc: gc_arena::CollectionContext) {}
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        broken: GcCell<'gc, Option<Gc<'gc, Broken<'gc>>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        broken: GcCell::allocate(mc, None),
+    });
+
+    arena.mutate(|mc, root| {
+        let broken = Broken {
+            strong: Gc::allocate(mc, 1),
+            weak: Gc::downgrade(Gc::allocate(mc, 2)),
+        };
+        *root.broken.write(mc) = Some(Gc::allocate(mc, broken));
+    });
+    let object_count = arena.stats().object_count;
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.stats().object_count, object_count - 2);
+
+    fn panic_message(result: std::thread::Result<()>) -> String {
+        let payload = result.unwrap_err();
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+        }
+    }
+
+    let deref = catch_unwind(AssertUnwindSafe(|| {
+        arena.mutate(|_, root| {
+            let _ = *root.broken.read().unwrap().strong;
+        })
+    }));
+    assert!(panic_message(deref).contains("use of freed object of type `i32`"));
+
+    let upgrade = catch_unwind(AssertUnwindSafe(|| {
+        arena.mutate(|mc, root| {
+            let _ = root.broken.read().unwrap().weak.upgrade(mc);
+        })
+    }));
+    assert!(panic_message(upgrade).contains("use of freed object of type `i32`"));
+}
+
+#[test]
+fn heap_snapshot() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        name: Gc<'gc, str>,
+        next: Option<Gc<'gc, Node<'gc>>>,
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        list: Gc<'gc, [Gc<'gc, Node<'gc>>]>,
+        weak: GcWeak<'gc, Node<'gc>>,
+        ephemerons: EphemeronTable<'gc, Node<'gc>, Gc<'gc, i32>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        let tail = Gc::allocate(
+            mc,
+            Node {
+                name: Gc::from_str(mc, "tail"),
+                next: None,
+            },
+        );
+        let head = Gc::allocate(
+            mc,
+            Node {
+                name: Gc::from_str(mc, "head"),
+                next: Some(tail),
+            },
+        );
+        let ephemerons = EphemeronTable::new(mc);
+        ephemerons.insert(mc, tail, Gc::allocate(mc, 1));
+        ephemerons.insert(
+            mc,
+            Gc::allocate(
+                mc,
+                Node {
+                    name: Gc::from_str(mc, "unreachable"),
+                    next: None,
+                },
+            ),
+            Gc::allocate(mc, 2),
+        );
+        TestRoot {
+            list: Gc::from_slice(mc, &[head]),
+            weak: Gc::downgrade(tail),
+            ephemerons,
+        }
+    });
+
+    // Snapshots do not disturb a collection in progress.
+    arena.mutate(|mc, _| {
+        for _ in 0..100 {
+            Gc::allocate(mc, [0u64; 8]);
+        }
+    });
+    arena.collect_debt();
+
+    let snapshot = arena.heap_snapshot();
+    arena.collect_all();
+    arena.collect_all();
+
+    arena.mutate(|_, root| {
+        let nodes = snapshot.nodes();
+        let edges = snapshot.edges();
+
+        // The root, the slice, both nodes and their names, the ephemeron table and the value for
+        // the reachable key.
+        assert_eq!(nodes.len(), 8);
+        assert!(nodes[HeapSnapshot::ROOT].type_name.contains("TestRoot"));
+        assert_eq!(
+            snapshot.total_size(),
+            nodes[1..].iter().map(|node| node.size).sum::<usize>()
+        );
+
+        let list = snapshot.find(root.list).unwrap();
+        let head = snapshot.find(root.list[0]).unwrap();
+        let tail = snapshot.find(root.list[0].next.unwrap()).unwrap();
+        let tail_name = snapshot.find(root.list[0].next.unwrap().name).unwrap();
+        assert!(nodes[head].type_name.contains("Node"));
+        assert_eq!(nodes[tail_name].type_name, "str");
+        assert_eq!(
+            nodes[head].address,
+            Gc::as_ptr(root.list[0]) as *const u8 as usize
+        );
+
+        let edge = |from, to, weak| HeapEdge { from, to, weak };
+        assert!(edges.contains(&edge(HeapSnapshot::ROOT, list, false)));
+        assert!(edges.contains(&edge(list, head, false)));
+        assert!(edges.contains(&edge(head, tail, false)));
+        assert!(edges.contains(&edge(tail, tail_name, false)));
+        assert!(edges.contains(&edge(HeapSnapshot::ROOT, tail, true)));
+        assert!(nodes.iter().any(|node| node.type_name == "i32"));
+
+        let mut dot = String::new();
+        snapshot.write_dot(&mut dot).unwrap();
+        assert!(dot.starts_with("digraph heap {"));
+        assert!(dot.contains(&format!("n{} -> n{};", head, tail)));
+        assert!(dot.contains(&format!("n0 -> n{} [style=dashed];", tail)));
+
+        let mut json = String::new();
+        snapshot.write_json(&mut json).unwrap();
+        assert!(json.contains("\"node_count\":8"));
+        assert!(json.contains(&format!("\"edge_count\":{}", edges.len())));
+        assert!(json.contains("\"str\""));
+    });
+}
+
+#[test]
+fn retaining_path() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        children: GcCell<'gc, Vec<Gc<'gc, Node<'gc>>>>,
+        weak: GcCell<'gc, Option<GcWeak<'gc, Node<'gc>>>>,
+    }
+
+    impl<'gc> Node<'gc> {
+        fn new(mc: MutationContext<'gc, '_>) -> Gc<'gc, Node<'gc>> {
+            Gc::allocate(
+                mc,
+                Node {
+                    children: GcCell::allocate(mc, Vec::new()),
+                    weak: GcCell::allocate(mc, None),
+                },
+            )
+        }
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        node: Gc<'gc, Node<'gc>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        node: Node::new(mc),
+    });
+
+    // root -> a -> target, and root -> b -> c -> target, with a weak pointer from b to an object
+    // that is otherwise unreachable.
+    arena.mutate(|mc, root| {
+        let a = Node::new(mc);
+        let b = Node::new(mc);
+        let c = Node::new(mc);
+        let target = Node::new(mc);
+        let unreachable = Node::new(mc);
+        root.node.children.write(mc).extend([b, a]);
+        b.children.write(mc).push(c);
+        *b.weak.write(mc) = Some(Gc::downgrade(unreachable));
+        c.children.write(mc).push(target);
+        a.children.write(mc).push(target);
+    });
+    arena.collect_debt();
+
+    arena.mutate(|mc, root| {
+        let snapshot = arena.heap_snapshot();
+        let children = root.node.children.read();
+        let (b, a) = (children[0], children[1]);
+        let target = a.children.read()[0];
+
+        // Each node holds its children through a `GcCell`.
+        fn cell(snapshot: &HeapSnapshot, node: usize) -> usize {
+            snapshot
+                .edges()
+                .iter()
+                .find(|edge| edge.from == node && !edge.weak)
+                .unwrap()
+                .to
+        }
+        let root_node = snapshot.find(root.node).unwrap();
+        let a_node = snapshot.find(a).unwrap();
+        let target_node = snapshot.find(target).unwrap();
+
+        let path = snapshot.retaining_path(target_node).unwrap();
+        assert_eq!(
+            path,
+            [
+                HeapSnapshot::ROOT,
+                root_node,
+                cell(&snapshot, root_node),
+                a_node,
+                cell(&snapshot, a_node),
+                target_node,
+            ]
+        );
+        assert!(snapshot.nodes()[path[1]].type_name.contains("Node"));
+        assert_eq!(
+            snapshot.retaining_path(HeapSnapshot::ROOT).unwrap(),
+            [HeapSnapshot::ROOT]
+        );
+
+        assert_eq!(snapshot.find_weak(Gc::downgrade(a)), Some(a_node));
+        assert_eq!(snapshot.find_weak(b.weak.read().unwrap()), None);
+
+        // The arena can find the same path directly, without taking a snapshot.
+        let names: Vec<_> = path
+            .iter()
+            .map(|&node| snapshot.nodes()[node].type_name)
+            .collect();
+        assert_eq!(arena.retaining_path(target).unwrap(), names);
+        assert_eq!(
+            arena.retaining_path_weak(Gc::downgrade(a)).unwrap(),
+            names[..4]
+        );
+        assert_eq!(arena.retaining_path_weak(b.weak.read().unwrap()), None);
+        assert_eq!(arena.retaining_path(Node::new(mc)), None);
+    });
+
+    // Walking the heap while a cell is mutably borrowed skips the contents of that cell rather than
+    // panicking.
+    arena.mutate(|mc, root| {
+        let children = root.node.children.write(mc);
+        let target = children[1].children.read()[0];
+        assert_eq!(arena.retaining_path(target), None);
+        assert_eq!(arena.heap_snapshot().find(target), None);
+        drop(children);
+        assert!(arena.retaining_path(target).is_some());
+    });
+}
+
+#[test]
+fn dominators() {
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(require_static)]
+    struct Blob([u8; 1024]);
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        children: GcCell<'gc, Vec<Gc<'gc, Node<'gc>>>>,
+        blob: Option<Gc<'gc, Blob>>,
+    }
+
+    impl<'gc> Node<'gc> {
+        fn new(mc: MutationContext<'gc, '_>, blob: bool) -> Gc<'gc, Node<'gc>> {
+            Gc::allocate(
+                mc,
+                Node {
+                    children: GcCell::allocate(mc, Vec::new()),
+                    blob: blob.then(|| Gc::allocate(mc, Blob([0; 1024]))),
+                },
+            )
+        }
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        node: Gc<'gc, Node<'gc>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    let arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        node: Node::new(mc, false),
+    });
+
+    // root -> a -> inner, and both a and b share a single node, so only inner and its blob are
+    // retained by a alone.
+    arena.mutate(|mc, root| {
+        let a = Node::new(mc, false);
+        let b = Node::new(mc, false);
+        let inner = Node::new(mc, true);
+        let shared = Node::new(mc, true);
+        root.node.children.write(mc).extend([a, b]);
+        a.children.write(mc).extend([inner, shared]);
+        b.children.write(mc).push(shared);
+    });
+
+    arena.mutate(|_, root| {
+        let snapshot = arena.heap_snapshot();
+        let dominators = snapshot.dominators();
+        let size = |node: usize| snapshot.nodes()[node].size;
+
+        let children = root.node.children.read();
+        let (a, b) = (children[0], children[1]);
+        let (inner, shared) = (a.children.read()[0], a.children.read()[1]);
+        // Each node holds its children through a `GcCell`, which is traced before its blob.
+        fn cell(snapshot: &HeapSnapshot, node: usize) -> usize {
+            snapshot
+                .edges()
+                .iter()
+                .find(|edge| edge.from == node)
+                .unwrap()
+                .to
+        }
+        let root_node = snapshot.find(root.node).unwrap();
+        let root_cell = cell(&snapshot, root_node);
+        let a_node = snapshot.find(a).unwrap();
+        let a_cell = cell(&snapshot, a_node);
+        let inner_node = snapshot.find(inner).unwrap();
+        let inner_cell = cell(&snapshot, inner_node);
+        let inner_blob = snapshot.find(inner.blob.unwrap()).unwrap();
+        let shared_node = snapshot.find(shared).unwrap();
+
+        assert_eq!(dominators.immediate_dominator(HeapSnapshot::ROOT), None);
+        assert_eq!(
+            dominators.immediate_dominator(root_node),
+            Some(HeapSnapshot::ROOT)
+        );
+        assert_eq!(dominators.immediate_dominator(inner_node), Some(a_cell));
+        assert_eq!(dominators.immediate_dominator(shared_node), Some(root_cell));
+        assert_eq!(dominators.dominated(a_node), [a_cell]);
+        assert!(dominators.dominated(root_cell).contains(&shared_node));
+        assert!(dominators.dominated(snapshot.find(b).unwrap()).len() == 1);
+
+        assert_eq!(
+            dominators.retained_size(HeapSnapshot::ROOT),
+            size(HeapSnapshot::ROOT) + snapshot.total_size()
+        );
+        assert_eq!(
+            dominators.retained_size(a_node),
+            size(a_node) + size(a_cell) + size(inner_node) + size(inner_cell) + size(inner_blob)
+        );
+        assert_eq!(dominators.retained_size(inner_blob), size(inner_blob));
+
+        let summaries = dominators.type_summaries(&snapshot);
+        assert!(summaries
+            .windows(2)
+            .all(|w| w[0].retained_size >= w[1].retained_size));
+        let summary = |name: &str| -> TypeSummary {
+            summaries
+                .iter()
+                .find(|summary| summary.type_name.ends_with(name))
+                .unwrap()
+                .clone()
+        };
+        let blobs = summary("::Blob");
+        assert_eq!(blobs.count, 2);
+        assert_eq!(blobs.shallow_size, 2 * size(inner_blob));
+        assert_eq!(blobs.retained_size, blobs.shallow_size);
+        // Every other node is dominated by the root node, so it is the only one counted.
+        let nodes = summary("::Node<'_>");
+        assert_eq!(nodes.count, 5);
+        assert_eq!(nodes.retained_size, dominators.retained_size(root_node));
+        assert_eq!(summaries[0].type_name, nodes.type_name);
+    });
+}
+
+#[test]
+fn census() {
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Entity<'gc> {
+        name: Gc<'gc, str>,
+    }
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct TestRoot<'gc> {
+        level: GcCell<'gc, Vec<Gc<'gc, Entity<'gc>>>>,
+        leaked: GcCell<'gc, Vec<Gc<'gc, Entity<'gc>>>>,
+    }
+    type TestArena = Arena<Rootable!['gc => TestRoot<'gc>]>;
+
+    fn load_level(arena: &TestArena, leak: bool) {
+        arena.mutate(|mc, root| {
+            let entities: Vec<_> = (0..10)
+                .map(|i| {
+                    Gc::allocate(
+                        mc,
+                        Entity {
+                            name: Gc::from_str(mc, &format!("entity {}", i)),
+                        },
+                    )
+                })
+                .collect();
+            if leak {
+                root.leaked.write(mc).push(entities[0]);
+            }
+            *root.level.write(mc) = entities;
+        });
+    }
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        level: GcCell::allocate(mc, Vec::new()),
+        leaked: GcCell::allocate(mc, Vec::new()),
+    });
+
+    load_level(&arena, false);
+    let before = arena.census();
+    let entity_type = before
+        .iter()
+        .map(|(type_name, _)| type_name)
+        .find(|type_name| type_name.ends_with("::Entity<'_>"))
+        .unwrap();
+    assert_eq!(before.get(entity_type).count, 10);
+    assert_eq!(before.get("str").count, 10);
+    assert_eq!(before.get("no such type"), Default::default());
+    assert_eq!(before.total_size(), arena.heap_snapshot().total_size());
+
+    // Reloading the level and collecting the old one should leave the heap exactly as it was.
+    load_level(&arena, false);
+    arena.collect_all();
+    let diff = before.diff(&arena.census());
+    assert!(diff.is_empty(), "{}", diff);
+
+    // The first entity of a leaky level is kept alive after the next reload.
+    load_level(&arena, true);
+    load_level(&arena, false);
+    arena.collect_all();
+    let after = arena.census();
+    assert_eq!(after.object_count(), before.object_count() + 2);
+    let diff = before.diff(&after);
+    assert!(diff.has_growth());
+    let growth: Vec<_> = diff.growth().map(|change| change.type_name).collect();
+    assert_eq!(growth.len(), 2);
+    assert!(growth.contains(&entity_type));
+    assert!(growth.contains(&"str"));
+    let entity_change = diff
+        .changes()
+        .iter()
+        .find(|change| change.type_name == entity_type)
+        .unwrap();
+    assert_eq!(entity_change.count_delta(), 1);
+    assert_eq!(
+        entity_change.size_delta() as usize,
+        after.get(entity_type).size / 11
+    );
+    assert!(diff
+        .to_string()
+        .contains(&format!("{}: +1 objects (10 -> 11)", entity_type)));
+
+    // Nothing shrinks, so the reverse diff reports the same changes without any growth.
+    let reverse = after.diff(&before);
+    assert_eq!(reverse.changes().len(), diff.changes().len());
+    assert!(!reverse.has_growth());
+}
+
+#[test]
+fn derive_collect() {
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Test1<'gc> {
+        a: i32,
+        b: Gc<'gc, i32>,
+    }
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Test2 {
+        a: i32,
+        b: i32,
+    }
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    enum Test3<'gc> {
+        B(Gc<'gc, i32>),
+        A(i32),
+    }
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    enum Test4 {
+        A(i32),
+    }
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Test5(Gc<'static, i32>);
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Test6(i32);
+
+    assert_eq!(Test1::needs_trace(), true);
+    assert_eq!(Test2::needs_trace(), false);
+    assert_eq!(Test3::needs_trace(), true);
+    assert_eq!(Test4::needs_trace(), false);
+    assert_eq!(Test5::needs_trace(), true);
+    assert_eq!(Test6::needs_trace(), false);
+
+    struct NoImpl;
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Test7 {
+        #[collect(require_static)]
+        field: NoImpl,
+    }
+
+    #[allow(unused)]
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    enum Test8 {
+        First {
+            #[collect(require_static)]
+            field: NoImpl,
+        },
+    }
+
+    assert_eq!(Test7::needs_trace(), false);
+    assert_eq!(Test8::needs_trace(), false);
+
+    assert!(!<Box<i32>>::needs_trace());
+    assert!(!<Box<[i32]>>::needs_trace());
+    assert!(<Box<[Gc<i32>]>>::needs_trace());
+    assert!(!<Box<str>>::needs_trace());
 }
 
 #[test]
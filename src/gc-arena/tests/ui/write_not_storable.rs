@@ -0,0 +1,34 @@
+use gc_arena::{Arena, Collect, Gc, Lock, Rootable, Write};
+
+#[derive(Collect)]
+#[collect(no_drop)]
+struct Node<'gc> {
+    value: Lock<i32>,
+    next: Lock<Option<Gc<'gc, Node<'gc>>>>,
+}
+
+#[derive(Collect)]
+#[collect(no_drop)]
+struct TestRoot<'gc> {
+    node: Gc<'gc, Node<'gc>>,
+    write: Option<&'gc Write<Node<'gc>>>,
+}
+
+fn main() {
+    let mut arena = Arena::<Rootable!['gc => TestRoot<'gc>]>::new(Default::default(), |mc| {
+        TestRoot {
+            node: Gc::allocate(
+                mc,
+                Node {
+                    value: Lock::new(0),
+                    next: Lock::new(None),
+                },
+            ),
+            write: None,
+        }
+    });
+
+    arena.mutate_root(|mc, root| {
+        root.write = Some(Gc::write(mc, root.node));
+    });
+}
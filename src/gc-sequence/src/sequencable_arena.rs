@@ -1,10 +1,10 @@
 /// Creates a set of types for running "sequences" on `gc_arena` "arena" types.
 ///
 /// Takes two parameters, the first is the name of a module that will be created, the second is the
-/// name of a type that can be made into an "arena" type with `gc_arena::make_arena`.
+/// name of a root type that could be held by a `gc_arena::Arena`.
 ///
 /// The module will contain two accessible types, `module::Arena` `module::Sequencer`.  The `Arena`
-/// type is the same as what would be produced by `gc_arena::make_arena`, but has a single extra
+/// type has the same methods as a `gc_arena::Arena` with that root, but has a single extra
 /// method `Arena::sequence`.  `Arena::sequence` can be used to produce a `module::Sequencer`, which
 /// can then be stepped until a result is produced.
 ///
@@ -62,7 +62,10 @@ macro_rules! make_sequencable_arena {
             use core::any::Any;
             use core::marker::PhantomData;
 
-            use gc_arena::{make_arena, ArenaParameters, Collect, GcCell, MutationContext};
+            use gc_arena::{
+                ArenaParameters, Collect, CollectionSummary, CollectorStats, GcCell,
+                MutationContext, Rootable, TypeStats,
+            };
             use gc_sequence::{Sequence, SequenceExt};
 
             use super::$root;
@@ -77,7 +80,7 @@ macro_rules! make_sequencable_arena {
                 >,
             }
 
-            make_arena!(InnerArena, InnerRoot);
+            type InnerArena = gc_arena::Arena<Rootable!['gc => InnerRoot<'gc>]>;
 
             $innervis struct Arena(InnerArena);
 
@@ -151,19 +154,33 @@ macro_rules! make_sequencable_arena {
                     self.0.allocation_debt()
                 }
 
+                /// Returns statistics about the garbage collector.
+                #[allow(unused)]
+                #[inline]
+                $innervis fn stats(&self) -> CollectorStats {
+                    self.0.stats()
+                }
+
+                /// Returns allocation statistics for every type ever allocated, only recorded
+                /// when the "profiling" feature of `gc-arena` is enabled.
+                #[allow(unused)]
+                $innervis fn type_stats(&self) -> Vec<TypeStats> {
+                    self.0.type_stats()
+                }
+
                 /// Runs the incremental garbage collector until the allocation debt is <= 0.0.
                 /// There is no minimum unit of work enforced here, so it may be faster to only call
                 /// this method when the allocation debt is above some threshold.
                 #[allow(unused)]
                 #[inline]
-                $innervis fn collect_debt(&mut self) {
+                $innervis fn collect_debt(&mut self) -> CollectionSummary {
                     self.0.collect_debt()
                 }
 
                 /// Run the current garbage collection cycle to completion, stopping once the
                 /// garbage collector has entered the sleeping phase.
                 #[allow(unused)]
-                $innervis fn collect_all(&mut self) {
+                $innervis fn collect_all(&mut self) -> CollectionSummary {
                     self.0.collect_all()
                 }
             }
@@ -217,12 +234,23 @@ macro_rules! make_sequencable_arena {
 
                 #[allow(unused)]
                 #[inline]
-                $innervis fn collect_debt(&mut self) {
+                $innervis fn stats(&self) -> CollectorStats {
+                    self.0.stats()
+                }
+
+                #[allow(unused)]
+                $innervis fn type_stats(&self) -> Vec<TypeStats> {
+                    self.0.type_stats()
+                }
+
+                #[allow(unused)]
+                #[inline]
+                $innervis fn collect_debt(&mut self) -> CollectionSummary {
                     self.0.collect_debt()
                 }
 
                 #[allow(unused)]
-                $innervis fn collect_all(&mut self) {
+                $innervis fn collect_all(&mut self) -> CollectionSummary {
                     self.0.collect_all()
                 }
             }
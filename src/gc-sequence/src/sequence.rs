@@ -1,5 +1,5 @@
 use alloc::boxed::Box;
-use gc_arena::{Collect, MutationContext};
+use gc_arena::{Collect, CollectionContext, MutationContext};
 
 /// A trait that describes a sequence of actions to perform, in between which garbage collection may
 /// take place.
@@ -12,10 +12,24 @@ pub trait Sequence<'gc>: Collect {
     fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output>;
 }
 
-impl<'gc, T: ?Sized + Sequence<'gc>> Sequence<'gc> for Box<T> {
+impl<'gc, T: Sequence<'gc>> Sequence<'gc> for Box<T> {
     type Output = T::Output;
 
     fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output> {
         T::step(&mut (*self), mc)
     }
 }
+
+impl<'gc, O> Sequence<'gc> for Box<dyn Sequence<'gc, Output = O> + 'gc> {
+    type Output = O;
+
+    fn step(&mut self, mc: MutationContext<'gc, '_>) -> Option<Self::Output> {
+        (**self).step(mc)
+    }
+}
+
+unsafe impl<'gc, O> Collect for Box<dyn Sequence<'gc, Output = O> + 'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        (**self).trace(cc)
+    }
+}